@@ -0,0 +1,173 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use flate2::read::GzDecoder;
+use flate2::{Compression, GzBuilder};
+
+use crate::checksum;
+use crate::config::{self, Config};
+use crate::error::{Error, Result};
+use crate::log;
+
+/// Archive dependencies and their extracted sources, per checksum
+const ARCHIVE_CACHE_PATH: &str = "~/.cbuild/archive-cache/";
+
+/// Marks an extracted archive as complete, so that interrupted extractions
+/// are redone
+const EXTRACT_READY: &str = ".cbuild-ready";
+
+/// Writes a `.tar.gz` of `files`, given as their path in the archive and
+/// on disk. Entries are sorted and their metadata fixed, so the same files
+/// always give the same archive.
+pub fn create(output: &Path, files: &[(String, PathBuf)]) -> Result<()> {
+    let mut files = files.to_vec();
+    files.sort();
+
+    let encoder = GzBuilder::new().mtime(0).write(File::create(output)?, Compression::best());
+    let mut builder = tar::Builder::new(encoder);
+    for (name, path) in &files {
+        let contents = std::fs::read(path)?;
+
+        let mut header = tar::Header::new_gnu();
+        header.set_size(contents.len() as u64);
+        header.set_mode(if is_executable(path) { 0o755 } else { 0o644 });
+        header.set_mtime(0);
+        header.set_uid(0);
+        header.set_gid(0);
+        header.set_entry_type(tar::EntryType::Regular);
+        builder.append_data(&mut header, name, contents.as_slice()).map_err(|e| {
+            Error::Library(format!("Failed to add {} to {}: {}", path.display(), output.display(), e))
+        })?;
+    }
+
+    builder.into_inner()?.finish()?;
+    Ok(())
+}
+
+/// Extracts the `.tar.gz` or `.tar` `archive` into `dir`
+pub fn extract(archive: &Path, dir: &Path) -> Result<()> {
+    let mut magic = [0; 2];
+    File::open(archive)?.read_exact(&mut magic).map_err(|e| {
+        Error::Library(format!("Failed to read {}: {}", archive.display(), e))
+    })?;
+
+    let file = File::open(archive)?;
+    let result = if magic == [0x1f, 0x8b] {
+        tar::Archive::new(Box::new(GzDecoder::new(file)) as Box<dyn Read>).unpack(dir)
+    }
+    else {
+        tar::Archive::new(Box::new(file) as Box<dyn Read>).unpack(dir)
+    };
+    result.map_err(|e| {
+        Error::Library(format!("Failed to extract {}: {}", archive.display(), e))
+    })
+}
+
+/// Downloads `url` to `output` with curl, returning `false` when the
+/// server doesn't have it
+pub fn download(config: &Config, url: &str, output: &Path) -> Result<bool> {
+    config::require_network(config, url)?;
+    log(config, &format!("Running command: curl {}", url));
+
+    let result = std::process::Command::new("curl")
+        .args(["--silent", "--show-error", "--location", "--write-out", "%{http_code}", "--output"])
+        .arg(output)
+        .arg(url)
+        .output()
+        .map_err(|e| Error::Library(format!("Failed to run curl: {}", e)))?;
+    if !result.status.success() {
+        return Err(Error::Library(format!("Failed to download {}: {}", url, String::from_utf8_lossy(&result.stderr).trim())));
+    }
+
+    match String::from_utf8_lossy(&result.stdout).trim() {
+        "200" => Ok(true),
+        "404" | "410" => {
+            let _ = std::fs::remove_file(output);
+            Ok(false)
+        },
+        status => {
+            let _ = std::fs::remove_file(output);
+            Err(Error::Library(format!("Failed to download {}: HTTP status {}", url, status)))
+        },
+    }
+}
+
+/// Copies or downloads the archive at `location`, a path or URL, into the
+/// cache, checks that it has the SHA-256 `sha256` and extracts it. Returns
+/// the extracted project, which is the single top-level directory of the
+/// archive if it has one.
+pub fn fetch(config: &Config, name: &str, location: &str, sha256: &str) -> Result<PathBuf> {
+    let file_name = location.trim_end_matches('/').rsplit(['/', '\\']).next().unwrap_or_default().to_string();
+    let stem = file_name.trim_end_matches(".gz").trim_end_matches(".tgz").trim_end_matches(".tar");
+    let cache = PathBuf::from(shellexpand::tilde(ARCHIVE_CACHE_PATH).to_string())
+        .join(format!("{}-{}", stem, &sha256[..16]));
+    let dir = cache.join("src");
+    if dir.join(EXTRACT_READY).exists() {
+        return project_root(&dir);
+    }
+
+    std::fs::create_dir_all(&cache)?;
+    let archive = cache.join(&file_name);
+    if config::is_url(location) {
+        config::require_network(config, location)?;
+        println!("Downloading {} from {}", name, location);
+        if !download(config, location, &archive)? {
+            return Err(Error::Library(format!("Archive of `{}` not found at {}", name, location)));
+        }
+    }
+    else {
+        let path = location.strip_prefix("file://").unwrap_or(location);
+        std::fs::copy(path, &archive).map_err(|e| {
+            Error::Library(format!("Failed to copy archive of `{}` from {}: {}", name, path, e))
+        })?;
+    }
+
+    let found = checksum::sha256_file(&archive)?;
+    if found != sha256 {
+        std::fs::remove_file(&archive)?;
+        return Err(Error::Library(format!(
+            "Checksum of the archive of `{}` does not match the manifest:\n  expected {}\n  found    {}\n  from     {}",
+            name, sha256, found, location)));
+    }
+
+    println!("Unpacking {}", file_name);
+    if dir.exists() {
+        std::fs::remove_dir_all(&dir)?;
+    }
+    std::fs::create_dir_all(&dir)?;
+    extract(&archive, &dir)?;
+    std::fs::remove_file(&archive)?;
+    std::fs::write(dir.join(EXTRACT_READY), "")?;
+
+    project_root(&dir)
+}
+
+/// The single top-level directory of an extracted archive, or `dir` itself
+/// when the archive has files at its top level or is laid out as a project
+/// with just `include/` or `src/`
+fn project_root(dir: &Path) -> Result<PathBuf> {
+    let mut entries = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.file_name().is_some_and(|name| name != EXTRACT_READY) {
+            entries.push(path);
+        }
+    }
+
+    match entries.as_slice() {
+        [single] if single.is_dir() && !single.ends_with("include") && !single.ends_with("src") => Ok(single.clone()),
+        _ => Ok(dir.to_path_buf()),
+    }
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::metadata(path).is_ok_and(|metadata| metadata.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(_path: &Path) -> bool {
+    false
+}
@@ -0,0 +1,967 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use crate::config::{Compiler, Config, Language, Standard, Target, Type};
+use crate::profile::{DebugInfo, OptLevel};
+use crate::error::{Error, Result};
+use crate::log;
+
+/// Directory under `bin/` holding object files and their dependency info
+const OBJECT_DIR: &str = "obj";
+
+/// Compilation database written to the project root
+const COMPILE_COMMANDS: &str = "compile_commands.json";
+
+/// A single translation unit and the command that compiles it
+#[derive(Clone, Debug)]
+pub struct CompileUnit {
+    pub source: PathBuf,
+    pub object: PathBuf,
+    pub language: Language,
+    pub compiler: &'static str,
+    pub args: Vec<String>,
+}
+
+impl CompileUnit {
+    /// Makefile-style dependency list written by the compiler
+    fn dep_file(&self) -> PathBuf {
+        self.object.with_extension("d")
+    }
+
+    /// Records the command and compiler version the object was built with
+    fn cmd_file(&self) -> PathBuf {
+        self.object.with_extension("cmd")
+    }
+}
+
+/// Artifacts of a successful `build_project`
+pub struct BuildOutput {
+    pub output_file: PathBuf,
+    pub units: Vec<CompileUnit>,
+    pub compiler_version: String,
+
+    /// What the project was built against
+    pub imports: Exports,
+
+    /// It or a library it was built against has C++ sources
+    pub has_cpp: bool,
+}
+
+/// Headers and libraries a library project passes on to the projects
+/// depending on it, including those of its own dependencies
+#[derive(Clone, Debug, Default)]
+pub struct Exports {
+    pub include_dirs: Vec<PathBuf>,
+
+    /// Libraries to link, every library before the ones it depends on
+    pub link_inputs: Vec<PathBuf>,
+
+    /// System libraries to link, in the same order
+    pub link_libraries: Vec<String>,
+
+    /// Compiler flags besides include directories, such as the `-D`
+    /// definitions of system dependencies. Each is a flag followed by its
+    /// arguments, like `-isystem DIR`.
+    pub compile_flags: Vec<Vec<String>>,
+
+    /// Linker flags besides libraries, such as the `-L` directories of
+    /// system dependencies, grouped with their arguments the same way
+    pub link_flags: Vec<Vec<String>>,
+
+    /// Some library has C++ sources, so the C++ runtime must be linked.
+    /// Only known for the libraries of cbuild projects once they are built,
+    /// see `BuildOutput::has_cpp`.
+    pub needs_cpp: bool,
+}
+
+impl Exports {
+    /// Adds everything `other` exports
+    pub fn extend(&mut self, other: &Exports) {
+        self.needs_cpp |= other.needs_cpp;
+        for dir in &other.include_dirs {
+            if !self.include_dirs.contains(dir) {
+                self.include_dirs.push(dir.clone());
+            }
+        }
+        // A library needed by several others must come after all of them
+        for input in &other.link_inputs {
+            self.link_inputs.retain(|existing| existing != input);
+            self.link_inputs.push(input.clone());
+        }
+        for library in &other.link_libraries {
+            self.link_libraries.retain(|existing| existing != library);
+            self.link_libraries.push(library.clone());
+        }
+        for (flags, other_flags) in [(&mut self.compile_flags, &other.compile_flags), (&mut self.link_flags, &other.link_flags)] {
+            for flag in other_flags {
+                if !flags.contains(flag) {
+                    flags.push(flag.clone());
+                }
+            }
+        }
+    }
+}
+
+/// Links `inputs` into `output`, skipping the link when an identical
+/// command already produced an output newer than every input
+pub struct LinkStep<'a> {
+    pub build_type: &'a Type,
+    pub inputs: Vec<PathBuf>,
+    pub libraries: Vec<String>,
+
+    /// Linker flags of the imported system dependencies
+    pub flags: Vec<String>,
+    pub has_cpp: bool,
+    pub output: PathBuf,
+    pub cmd_file: PathBuf,
+}
+
+impl LinkStep<'_> {
+    /// Runs the link unless it is up to date and `force` is unset
+    pub fn run(&self, config: &Config, compiler_version: &str, force: bool) -> Result<()> {
+        let (linker, link_args) = link_command(config, self.build_type, &self.inputs, &self.libraries, &self.flags, &self.output, self.has_cpp);
+        let link_fingerprint = fingerprint(linker, compiler_version, &link_args);
+
+        let up_to_date = !force
+            && stored_fingerprint(&self.cmd_file).is_some_and(|stored| stored == link_fingerprint)
+            && is_newer_than_all(&self.output, &self.inputs);
+
+        if up_to_date {
+            log(config, &format!("Up to date: {}", self.output.display()));
+            return Ok(());
+        }
+
+        if let Type::Library = self.build_type {
+            // `ar` only adds members, so objects of deleted sources would linger
+            if self.output.exists() {
+                std::fs::remove_file(&self.output)?;
+            }
+        }
+        if let Some(parent) = self.output.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        report(&run_compiler(config, linker, &link_args)?)?;
+        std::fs::write(&self.cmd_file, link_fingerprint)?;
+        Ok(())
+    }
+}
+
+/// Builds the project of `config` against the libraries in `imports`,
+/// writing its `compile_commands.json` when `compile_commands` is set
+pub fn build_project(config: &Config, imports: &Exports, compile_commands: bool) -> Result<BuildOutput> {
+    log(config, "Starting build process");
+
+    let bin_path = profile_dir(config);
+    let obj_path = object_dir(config);
+    std::fs::create_dir_all(&obj_path)?;
+
+    let project_name = config.project_name.as_ref().ok_or_else(|| Error::Config("Project name not found".to_string()))?;
+    let output_file = bin_path.join(output_file_name(config, project_name));
+
+    let compiler_version = compiler_version(&config.settings.compiler);
+    let units = project_units(config, imports)?;
+    if compile_commands {
+        write_compile_commands(config, &config.root, &units)?;
+    }
+
+    let compiled = compile_stale(config, &units, &compiler_version)?;
+
+    let mut inputs: Vec<PathBuf> = units.iter().map(|unit| unit.object.clone()).collect();
+    // Static libraries are only combined when linking an executable or a
+    // shared library
+    if !matches!(config.settings.build_type, Type::Library) {
+        inputs.extend(imports.link_inputs.iter().cloned());
+    }
+
+    let has_cpp = imports.needs_cpp || units.iter().any(|unit| matches!(unit.language, Language::CPP));
+    let link = LinkStep {
+        build_type: &config.settings.build_type,
+        inputs,
+        libraries: link_libraries(config, imports),
+        flags: imports.link_flags.concat(),
+        has_cpp,
+        output: output_file.clone(),
+        cmd_file: obj_path.join(format!("{}.link.cmd", project_name)),
+    };
+    link.run(config, &compiler_version, compiled > 0)?;
+
+    if let (Type::DynLibrary, Compiler::GCC | Compiler::CLANG) = (&config.settings.build_type, &config.settings.compiler) {
+        link_versioned_names(config, project_name, &bin_path)?;
+    }
+
+    // @TODO: don't print on `run` mode
+    println!("Built `{}` ({} of {} files compiled)", project_name, compiled, units.len());
+    Ok(BuildOutput { output_file, units, compiler_version, imports: imports.clone(), has_cpp })
+}
+
+/// What the project of `config` passes on to its dependents when built
+/// against `imports`
+pub fn exports(config: &Config, imports: &Exports) -> Exports {
+    let project_name = config.project_name.as_deref().unwrap_or_default();
+    let artifact = match (&config.settings.build_type, &config.settings.compiler) {
+        // A DLL is linked through its import library
+        (Type::DynLibrary, Compiler::MSVC) => format!("{}.lib", project_name),
+        _ => output_file_name(config, project_name),
+    };
+
+    let mut exports = Exports {
+        include_dirs: vec![public_include_dir(config)],
+        link_inputs: vec![profile_dir(config).join(artifact)],
+        link_libraries: config.link_libraries.clone(),
+        ..Exports::default()
+    };
+    exports.extend(imports);
+    exports
+}
+
+/// System libraries the project of `config` is linked with, its own
+/// followed by those of its dependencies
+pub fn link_libraries(config: &Config, imports: &Exports) -> Vec<String> {
+    let mut libraries = Exports {
+        link_libraries: config.link_libraries.clone(),
+        ..Exports::default()
+    };
+    libraries.extend(imports);
+    libraries.link_libraries
+}
+
+/// Headers a library offers its dependents: `include/` when the project
+/// has one, otherwise `src/`, or the project root for header-only
+/// libraries with neither
+pub fn public_include_dir(config: &Config) -> PathBuf {
+    ["include", "src"].iter()
+        .map(|dir| config.root.join(dir))
+        .find(|dir| dir.is_dir())
+        .unwrap_or_else(|| config.root.clone())
+}
+
+/// Directory holding the outputs of the selected profile, such as
+/// `bin/release/`, so that switching profiles doesn't clobber artifacts
+pub fn profile_dir(config: &Config) -> PathBuf {
+    config.target_dir.join(&config.profile.name)
+}
+
+/// Object directory of the project within the selected profile, separate
+/// for each project since workspace members share the profile directory
+pub fn object_dir(config: &Config) -> PathBuf {
+    profile_dir(config)
+        .join(OBJECT_DIR)
+        .join(config.project_name.as_deref().unwrap_or_default())
+}
+
+/// Compiles every unit whose object is out of date, returning how many
+/// were compiled
+pub fn compile_stale(config: &Config, units: &[CompileUnit], compiler_version: &str) -> Result<usize> {
+    let mut stale = Vec::new();
+    for unit in units {
+        let fingerprint = fingerprint(unit.compiler, compiler_version, &unit.args);
+        if needs_rebuild(unit, &fingerprint) {
+            stale.push((unit, fingerprint));
+        }
+        else {
+            log(config, &format!("Up to date: {}", unit.source.display()));
+        }
+    }
+
+    compile_units(config, &stale)?;
+    Ok(stale.len())
+}
+
+/// Compile units for the project's own sources
+fn project_units(config: &Config, imports: &Exports) -> Result<Vec<CompileUnit>> {
+    let source_files = discover_sources(config, &config.root)?;
+    if source_files.is_empty() {
+        return Err(Error::Config(format!("No source files found matching {}", config.sources.join(", "))));
+    }
+
+    let mut include_dirs = vec![config.root.join("lib"), public_include_dir(config)];
+    include_dirs.extend(imports.include_dirs.iter().cloned());
+    Ok(plan_units(config, &config.root, &object_dir(config), source_files, &include_dirs, &imports.compile_flags.concat()))
+}
+
+/// Turns `source_files` of the project at `root` into compile commands,
+/// with objects placed under `obj_path` in a tree mirroring the sources and
+/// `extra_flags` added to the flags of the profile
+pub fn plan_units(
+    config: &Config,
+    root: &Path,
+    obj_path: &Path,
+    source_files: Vec<(PathBuf, Language)>,
+    include_dirs: &[PathBuf],
+    extra_flags: &[String],
+) -> Vec<CompileUnit> {
+    let mut c_flags = compile_flags(config, include_dirs, &Language::C);
+    let mut cpp_flags = compile_flags(config, include_dirs, &Language::CPP);
+    c_flags.extend(extra_flags.iter().cloned());
+    cpp_flags.extend(extra_flags.iter().cloned());
+
+    source_files.into_iter()
+        .map(|(source, language)| {
+            // Mirror the source tree so `a/util.c` and `b/util.c` don't clash
+            let relative = source.strip_prefix(root).unwrap_or(&source);
+            let mut object = obj_path.join(relative).into_os_string();
+            object.push(format!(".{}", object_extension(&config.settings.compiler)));
+            let object = PathBuf::from(object);
+            let flags = match language {
+                Language::C => &c_flags,
+                Language::CPP => &cpp_flags,
+            };
+            let args = compile_args(&config.settings.compiler, flags, &source, &object);
+            let compiler = compiler_driver(&config.settings.compiler, &language);
+            CompileUnit { source, object, language, compiler, args }
+        })
+        .collect()
+}
+
+/// Writes `compile_commands.json` for the project without building it
+pub fn generate_compile_commands(config: &Config, imports: &Exports) -> Result<()> {
+    let units = project_units(config, imports)?;
+    write_compile_commands(config, &config.root, &units)?;
+
+    println!("Wrote {}", config.root.join(COMPILE_COMMANDS).display());
+    Ok(())
+}
+
+/// Writes the compilation database used by clangd and similar tools to
+/// the project root, leaving the file untouched when nothing changed
+fn write_compile_commands(config: &Config, root: &Path, units: &[CompileUnit]) -> Result<()> {
+    let entries: Vec<serde_json::Value> = units.iter()
+        .map(|unit| {
+            let mut arguments = vec![unit.compiler.to_string()];
+            arguments.extend(unit.args.iter().cloned());
+            serde_json::json!({
+                "directory": root,
+                "arguments": arguments,
+                "file": unit.source,
+                "output": unit.object,
+            })
+        })
+        .collect();
+
+    let mut contents = serde_json::to_string_pretty(&entries)
+        .map_err(|e| Error::Config(e.to_string()))?;
+    contents.push('\n');
+
+    let path = root.join(COMPILE_COMMANDS);
+    if std::fs::read_to_string(&path).is_ok_and(|existing| existing == contents) {
+        return Ok(());
+    }
+
+    log(config, &format!("Writing {}", path.display()));
+    std::fs::write(path, contents)?;
+    Ok(())
+}
+
+/// Finds every file matching one of the `sources` patterns and none of the
+/// `exclude` patterns that has a C or C++ extension
+fn discover_sources(config: &Config, root: &Path) -> Result<Vec<(PathBuf, Language)>> {
+    let compile = |patterns: &[String]| -> Result<Vec<glob::Pattern>> {
+        patterns.iter()
+            .map(|pattern| glob::Pattern::new(pattern).map_err(|e| {
+                Error::Config(format!("Invalid glob pattern `{}`: {}", pattern, e.msg))
+            }))
+            .collect()
+    };
+    let sources = compile(&config.sources)?;
+    let exclude = compile(&config.exclude)?;
+
+    let options = glob::MatchOptions {
+        require_literal_separator: true,
+        ..glob::MatchOptions::new()
+    };
+
+    let mut directories: Vec<PathBuf> = config.sources.iter()
+        .map(|pattern| root.join(literal_prefix(pattern)))
+        .collect();
+    directories.sort();
+    directories.dedup();
+
+    let mut source_files = Vec::new();
+    while let Some(dir) = directories.pop() {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            // Symlinked directories are not followed, as they can loop back
+            // to one of their parents
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            if file_type.is_dir() {
+                directories.push(path);
+                continue;
+            }
+            if file_type.is_symlink() && path.is_dir() {
+                continue;
+            }
+
+            let relative = path.strip_prefix(root).unwrap_or(&path);
+            let selected = sources.iter().any(|pattern| pattern.matches_path_with(relative, options))
+                && !exclude.iter().any(|pattern| pattern.matches_path_with(relative, options));
+            if !selected {
+                continue;
+            }
+
+            if let Some(language) = source_language(&path) {
+                source_files.push((path, language));
+            }
+        }
+    }
+
+    source_files.sort_by(|(a, _), (b, _)| a.cmp(b));
+    source_files.dedup_by(|(a, _), (b, _)| a == b);
+
+    for (path, _) in &source_files {
+        log(config, &format!("Source: {}", path.strip_prefix(root).unwrap_or(path).display()));
+    }
+
+    Ok(source_files)
+}
+
+/// Leading directories of a glob pattern that contain no wildcards, which
+/// is where the search for matching files starts
+fn literal_prefix(pattern: &str) -> PathBuf {
+    let components: Vec<&str> = pattern.split(['/', '\\']).collect();
+    components[..components.len() - 1].iter()
+        .take_while(|component| !component.contains(['*', '?', '[']))
+        .collect()
+}
+
+/// Number of compiler processes to run at once
+pub fn job_count(config: &Config) -> usize {
+    config.jobs.unwrap_or_else(|| {
+        std::thread::available_parallelism().map_or(1, |jobs| jobs.get())
+    })
+}
+
+/// Compiles `units` with up to `job_count` concurrent compiler processes,
+/// recording each fingerprint once its object is built
+///
+/// After the first failure no new units are started unless `keep_going` is
+/// set, and the first error is returned once all running jobs finished.
+fn compile_units(config: &Config, units: &[(&CompileUnit, String)]) -> Result<()> {
+    let jobs = job_count(config).min(units.len());
+    if jobs == 0 {
+        return Ok(());
+    }
+    log(config, &format!("Compiling {} files with {} jobs", units.len(), jobs));
+
+    let next = AtomicUsize::new(0);
+    let failed = AtomicBool::new(false);
+    let first_error = Mutex::new(None);
+
+    std::thread::scope(|scope| {
+        for _ in 0..jobs {
+            scope.spawn(|| loop {
+                if failed.load(Ordering::SeqCst) && !config.keep_going {
+                    break;
+                }
+
+                let Some((unit, fingerprint)) = units.get(next.fetch_add(1, Ordering::SeqCst)) else {
+                    break;
+                };
+
+                let result = compile_unit(config, unit)
+                    .and_then(|_| Ok(std::fs::write(unit.cmd_file(), fingerprint)?));
+
+                if let Err(e) = result {
+                    failed.store(true, Ordering::SeqCst);
+                    first_error.lock().unwrap().get_or_insert(e);
+                }
+            });
+        }
+    });
+
+    match first_error.into_inner().unwrap() {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
+
+pub fn compiler_command(compiler: &Compiler) -> &'static str {
+    match compiler {
+        Compiler::GCC => "gcc",
+        Compiler::CLANG => "clang",
+        Compiler::MSVC => "cl.exe",
+    }
+}
+
+/// Compiler driver for sources of `language`, the C++ drivers also link
+/// the C++ standard library
+pub fn compiler_driver(compiler: &Compiler, language: &Language) -> &'static str {
+    match (compiler, language) {
+        (Compiler::GCC, Language::CPP) => "g++",
+        (Compiler::CLANG, Language::CPP) => "clang++",
+        _ => compiler_command(compiler),
+    }
+}
+
+/// Language of a source file, judging by its extension
+pub fn source_language(path: &Path) -> Option<Language> {
+    match path.extension()?.to_str()? {
+        "c" => Some(Language::C),
+        "cpp" | "cc" | "cxx" | "C" => Some(Language::CPP),
+        _ => None,
+    }
+}
+
+/// File name of the artifact `build_project` produces under `bin/`
+pub fn output_file_name(config: &Config, project_name: &str) -> String {
+    match (&config.settings.build_type, &config.settings.compiler) {
+        (Type::Binary, _) => project_name.to_string(),
+        (Type::Library, Compiler::MSVC) => format!("{}.lib", project_name),
+        (Type::Library, _) => format!("lib{}.a", project_name),
+        (Type::DynLibrary, Compiler::MSVC) => format!("{}.dll", project_name),
+        (Type::DynLibrary, _) => match &config.project_version {
+            Some(version) => format!("lib{}.so.{}", project_name, version),
+            None => format!("lib{}.so", project_name),
+        },
+    }
+}
+
+/// `DT_SONAME` of a shared library, which only carries the major version
+fn soname(config: &Config, project_name: &str) -> String {
+    match config.project_version.as_ref().and_then(|version| version.split('.').next()) {
+        Some(major) => format!("lib{}.so.{}", project_name, major),
+        None => format!("lib{}.so", project_name),
+    }
+}
+
+/// Creates the `lib<name>.so -> lib<name>.so.<major> -> lib<name>.so.<version>`
+/// symlink chain next to a versioned shared library
+fn link_versioned_names(config: &Config, project_name: &str, bin_path: &Path) -> Result<()> {
+    let real_name = output_file_name(config, project_name);
+    let soname = soname(config, project_name);
+    let dev_name = format!("lib{}.so", project_name);
+
+    for (link, target) in [(&soname, &real_name), (&dev_name, &soname)] {
+        if link == target {
+            continue;
+        }
+
+        let link_path = bin_path.join(link);
+        if link_path.symlink_metadata().is_ok() {
+            std::fs::remove_file(&link_path)?;
+        }
+        log(config, &format!("Linking {} -> {}", link, target));
+
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(target, &link_path)?;
+        #[cfg(not(unix))]
+        std::fs::copy(bin_path.join(target), &link_path)?;
+    }
+
+    Ok(())
+}
+
+/// Whether `path` names a shared object such as `libfoo.so` or
+/// `libfoo.so.1.2`
+fn is_shared_library(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.ends_with(".so") || name.contains(".so."))
+}
+
+fn object_extension(compiler: &Compiler) -> &'static str {
+    match compiler {
+        Compiler::GCC | Compiler::CLANG => "o",
+        Compiler::MSVC => "obj",
+    }
+}
+
+/// Flags shared by every translation unit of the project written in
+/// `language`
+fn compile_flags(config: &Config, include_dirs: &[PathBuf], language: &Language) -> Vec<String> {
+    let standard = match language {
+        Language::C => &config.settings.c_standard,
+        Language::CPP => &config.settings.cpp_standard,
+    };
+
+    let mut args = Vec::new();
+    match config.settings.compiler {
+        Compiler::GCC | Compiler::CLANG => {
+            args.extend(include_dirs.iter().map(|dir| format!("-I{}", dir.to_str().unwrap())));
+
+            args.push(match standard {
+                Standard::C89 => "-std=c89".to_string(),
+                Standard::C99 => "-std=c99".to_string(),
+                Standard::C11 => "-std=c11".to_string(),
+                Standard::C17 => "-std=c17".to_string(),
+                Standard::CPP98 => "-std=c++98".to_string(),
+                Standard::CPP11 => "-std=c++11".to_string(),
+                Standard::CPP14 => "-std=c++14".to_string(),
+                Standard::CPP17 => "-std=c++17".to_string(),
+                Standard::CPP20 => "-std=c++20".to_string(),
+            });
+
+            args.push(match config.profile.opt_level {
+                OptLevel::O0 => "-O0".to_string(),
+                OptLevel::O1 => "-O1".to_string(),
+                OptLevel::O2 => "-O2".to_string(),
+                OptLevel::O3 => "-O3".to_string(),
+                OptLevel::Os => "-Os".to_string(),
+                OptLevel::Oz => match config.settings.compiler {
+                    Compiler::CLANG => "-Oz".to_string(),
+                    _ => "-Os".to_string(),
+                },
+            });
+
+            match config.profile.debug {
+                DebugInfo::None => (),
+                DebugInfo::Limited => args.push("-g1".to_string()),
+                DebugInfo::Full => args.push("-g".to_string()),
+            }
+
+            if !config.profile.assertions {
+                args.push("-DNDEBUG".to_string());
+            }
+
+            if config.settings.target == Target::X86_64 {
+                args.push("-m64".to_string());
+            }
+
+            // Static libraries may end up inside a shared library that
+            // depends on them
+            if let Type::Library | Type::DynLibrary = config.settings.build_type {
+                args.push("-fPIC".to_string());
+            }
+        },
+        Compiler::MSVC => {
+            args.extend(include_dirs.iter().map(|dir| format!("/I{}", dir.to_str().unwrap())));
+
+            args.push(match standard {
+                Standard::C89 => "/Za".to_string(),
+                Standard::C99 | Standard::C11 | Standard::C17 => "/std:c11".to_string(),
+                Standard::CPP98 | Standard::CPP11 | Standard::CPP14 => "/std:c++14".to_string(),
+                Standard::CPP17 => "/std:c++17".to_string(),
+                Standard::CPP20 => "/std:c++latest".to_string(),
+            });
+
+            args.push(match config.profile.opt_level {
+                OptLevel::O0 => "/Od".to_string(),
+                OptLevel::O1 | OptLevel::O2 | OptLevel::O3 => "/O2".to_string(),
+                OptLevel::Os | OptLevel::Oz => "/O1".to_string(),
+            });
+
+            if config.profile.debug != DebugInfo::None {
+                args.push("/Zi".to_string());
+            }
+
+            if !config.profile.assertions {
+                args.push("/DNDEBUG".to_string());
+            }
+        },
+    }
+
+    args.extend(config.profile.flags.iter().cloned());
+    args
+}
+
+/// Full argument list compiling `source` into `object`
+fn compile_args(compiler: &Compiler, flags: &[String], source: &Path, object: &Path) -> Vec<String> {
+    let mut args = flags.to_vec();
+    match compiler {
+        Compiler::GCC | Compiler::CLANG => {
+            args.push("-c".to_string());
+            args.push(format!("-o{}", object.to_str().unwrap()));
+        },
+        Compiler::MSVC => {
+            args.push("/c".to_string());
+            args.push(format!("/Fo:{}", object.to_str().unwrap()));
+        },
+    }
+    args.push(source.to_str().unwrap().to_string());
+    args
+}
+
+/// Program and arguments producing `output_file` of `build_type` from
+/// `inputs` and the system `libraries`
+fn link_command(
+    config: &Config,
+    build_type: &Type,
+    inputs: &[PathBuf],
+    libraries: &[String],
+    flags: &[String],
+    output_file: &Path,
+    has_cpp: bool,
+) -> (&'static str, Vec<String>) {
+    let object_args = inputs.iter().map(|path| path.to_str().unwrap().to_string());
+    let mut args = Vec::new();
+
+    let linker = match (build_type, &config.settings.compiler) {
+        (Type::Library, Compiler::MSVC) => {
+            args.push(format!("/OUT:{}", output_file.to_str().unwrap()));
+            args.extend(object_args);
+            "lib.exe"
+        },
+        (Type::Library, _) => {
+            args.push("rcs".to_string());
+            args.push(output_file.to_str().unwrap().to_string());
+            args.extend(object_args);
+            "ar"
+        },
+        (build_type, Compiler::GCC | Compiler::CLANG) => {
+            if let Type::DynLibrary = build_type {
+                let project_name = config.project_name.as_deref().unwrap_or_default();
+                args.push("-shared".to_string());
+                args.push(format!("-Wl,-soname,{}", soname(config, project_name)));
+            }
+            args.push(format!("-o{}", output_file.to_str().unwrap()));
+            if config.profile.strip {
+                args.push("-s".to_string());
+            }
+            if config.settings.target == Target::X86_64 {
+                args.push("-m64".to_string());
+            }
+            args.extend(object_args);
+
+            // Let the output find the shared libraries it was linked against
+            let mut library_dirs: Vec<&Path> = inputs.iter()
+                .filter(|input| is_shared_library(input))
+                .filter_map(|input| input.parent())
+                .collect();
+            library_dirs.dedup();
+            args.extend(library_dirs.iter().map(|dir| format!("-Wl,-rpath,{}", dir.to_str().unwrap())));
+
+            args.extend(flags.iter().cloned());
+            args.extend(libraries.iter().map(|library| format!("-l{}", library)));
+            args.extend(config.profile.link_flags.iter().cloned());
+            let language = if has_cpp { Language::CPP } else { Language::C };
+            compiler_driver(&config.settings.compiler, &language)
+        },
+        (build_type, Compiler::MSVC) => {
+            if let Type::DynLibrary = build_type {
+                args.push("/LD".to_string());
+            }
+            args.push(format!("/Fe:{}", output_file.to_str().unwrap()));
+            args.extend(object_args);
+            args.extend(libraries.iter().map(|library| format!("{}.lib", library)));
+            args.push("/link".to_string());
+            args.extend(flags.iter().cloned());
+            if config.settings.target == Target::X86_64 {
+                args.push("/MACHINE:X64".to_string());
+            }
+            args.extend(config.profile.link_flags.iter().cloned());
+            compiler_command(&config.settings.compiler)
+        },
+    };
+
+    (linker, args)
+}
+
+/// First line of the compiler's version banner, so that upgrading the
+/// toolchain invalidates existing objects
+pub fn compiler_version(compiler: &Compiler) -> String {
+    let output = match compiler {
+        Compiler::GCC | Compiler::CLANG => {
+            std::process::Command::new(compiler_command(compiler)).arg("--version").output()
+        },
+        Compiler::MSVC => std::process::Command::new(compiler_command(compiler)).output(),
+    };
+
+    output.ok()
+        .and_then(|output| {
+            let banner = if output.stdout.is_empty() { output.stderr } else { output.stdout };
+            String::from_utf8_lossy(&banner).lines().next().map(str::to_string)
+        })
+        .unwrap_or_default()
+}
+
+fn fingerprint(compiler: &str, version: &str, args: &[String]) -> String {
+    format!("{}\n{} {}\n", version, compiler, args.join(" "))
+}
+
+fn stored_fingerprint(path: &Path) -> Option<String> {
+    std::fs::read_to_string(path).ok()
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
+
+/// Whether `output` exists and is at least as new as every one of `inputs`
+fn is_newer_than_all(output: &Path, inputs: &[PathBuf]) -> bool {
+    let Some(output_time) = modified(output) else {
+        return false;
+    };
+    inputs.iter().all(|input| modified(input).is_some_and(|time| time <= output_time))
+}
+
+/// Whether the object of `unit` is missing or stale with respect to its
+/// source, the headers it included last time, or its compile command
+fn needs_rebuild(unit: &CompileUnit, fingerprint: &str) -> bool {
+    if stored_fingerprint(&unit.cmd_file()).as_deref() != Some(fingerprint) {
+        return true;
+    }
+
+    let Ok(contents) = std::fs::read_to_string(unit.dep_file()) else {
+        return true;
+    };
+
+    let mut inputs = parse_dep_file(&contents);
+    inputs.push(unit.source.clone());
+    !is_newer_than_all(&unit.object, &inputs)
+}
+
+/// Parses the prerequisites out of a Makefile rule as written by `-MMD`
+fn parse_dep_file(contents: &str) -> Vec<PathBuf> {
+    let joined = contents.replace("\\\r\n", " ").replace("\\\n", " ");
+    let mut dependencies = Vec::new();
+
+    for rule in joined.lines() {
+        let Some((_, prerequisites)) = rule.split_once(": ") else {
+            continue;
+        };
+
+        let mut current = String::new();
+        let mut chars = prerequisites.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' if chars.peek() == Some(&' ') => {
+                    current.push(' ');
+                    chars.next();
+                },
+                ' ' | '\t' => {
+                    if !current.is_empty() {
+                        dependencies.push(PathBuf::from(std::mem::take(&mut current)));
+                    }
+                },
+                _ => current.push(c),
+            }
+        }
+        if !current.is_empty() {
+            dependencies.push(PathBuf::from(current));
+        }
+    }
+
+    dependencies
+}
+
+fn compile_unit(config: &Config, unit: &CompileUnit) -> Result<()> {
+    let dep_file = unit.dep_file();
+    let mut args = unit.args.clone();
+    if let Compiler::MSVC = config.settings.compiler {
+        args.insert(0, "/showIncludes".to_string());
+    }
+    else {
+        args.insert(0, "-MMD".to_string());
+        args.insert(1, format!("-MF{}", dep_file.to_str().unwrap()));
+    }
+
+    if let Some(parent) = unit.object.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    log(config, &format!("Compiling {}", unit.source.display()));
+    let mut output = run_compiler(config, unit.compiler, &args)?;
+
+    if let Compiler::MSVC = config.settings.compiler {
+        output.stdout = write_msvc_dep_file(unit, &String::from_utf8_lossy(&output.stdout))?.into_bytes();
+    }
+
+    report(&output)
+}
+
+/// Converts `/showIncludes` notes into the same format `-MMD` produces,
+/// returning the remaining compiler output
+fn write_msvc_dep_file(unit: &CompileUnit, stdout: &str) -> Result<String> {
+    const PREFIX: &str = "Note: including file:";
+
+    let mut contents = format!("{}: {}", unit.object.display(), unit.source.display());
+    let mut remaining = String::new();
+    for line in stdout.lines() {
+        if let Some(header) = line.strip_prefix(PREFIX) {
+            contents.push_str(" \\\n  ");
+            contents.push_str(&header.trim().replace(' ', "\\ "));
+        }
+        else {
+            remaining.push_str(line);
+            remaining.push('\n');
+        }
+    }
+    contents.push('\n');
+
+    std::fs::write(unit.dep_file(), contents)?;
+    Ok(remaining)
+}
+
+fn run_compiler(config: &Config, compiler: &str, args: &[String]) -> Result<std::process::Output> {
+    log(config, &format!("Running command: {} {}", compiler, args.join(" ")));
+
+    Ok(std::process::Command::new(compiler)
+        .args(args)
+        .current_dir(&config.root)
+        .output()?)
+}
+
+/// Prints the diagnostics of a finished compiler process in one piece, so
+/// that output of concurrent jobs never interleaves
+fn report(output: &std::process::Output) -> Result<()> {
+    {
+        let mut stderr = std::io::stderr().lock();
+        stderr.write_all(&output.stdout)?;
+        stderr.write_all(&output.stderr)?;
+    }
+
+    if !output.status.success() {
+        return Err(Error::BuildFailed());
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn literal_prefix_stops_at_the_first_wildcard() {
+        assert_eq!(literal_prefix("src/**"), PathBuf::from("src"));
+        assert_eq!(literal_prefix("src/platform/*.c"), PathBuf::from("src/platform"));
+        assert_eq!(literal_prefix("src/*/impl/*.c"), PathBuf::from("src"));
+        assert_eq!(literal_prefix("lib/[ab]/x.c"), PathBuf::from("lib"));
+        assert_eq!(literal_prefix("src\\win32\\*.c"), PathBuf::from("src").join("win32"));
+    }
+
+    #[test]
+    fn literal_prefix_excludes_the_file_name() {
+        assert_eq!(literal_prefix("src/main.c"), PathBuf::from("src"));
+        assert_eq!(literal_prefix("main.c"), PathBuf::new());
+        assert_eq!(literal_prefix("**/*.c"), PathBuf::new());
+    }
+
+    #[test]
+    fn parses_dep_file_prerequisites() {
+        let dependencies = parse_dep_file("bin/debug/obj/main.o: src/main.c src/util.h\n");
+        assert_eq!(dependencies, [PathBuf::from("src/main.c"), PathBuf::from("src/util.h")]);
+    }
+
+    #[test]
+    fn parses_dep_file_line_continuations() {
+        let contents = "bin/debug/obj/main.o: src/main.c \\\n  src/util.h \\\r\n  include/api.h\n";
+        let dependencies = parse_dep_file(contents);
+        assert_eq!(dependencies, [
+            PathBuf::from("src/main.c"),
+            PathBuf::from("src/util.h"),
+            PathBuf::from("include/api.h"),
+        ]);
+    }
+
+    #[test]
+    fn parses_dep_file_escaped_spaces() {
+        let dependencies = parse_dep_file("obj/main.o: my\\ project/main.c my\\ project/a\\ b.h\n");
+        assert_eq!(dependencies, [PathBuf::from("my project/main.c"), PathBuf::from("my project/a b.h")]);
+    }
+
+    #[test]
+    fn parses_every_rule_of_a_dep_file() {
+        // `-MP` adds an empty rule for each header
+        let dependencies = parse_dep_file("obj/main.o: src/main.c src/util.h\n\nsrc/util.h:\n");
+        assert_eq!(dependencies, [PathBuf::from("src/main.c"), PathBuf::from("src/util.h")]);
+    }
+}
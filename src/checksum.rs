@@ -0,0 +1,62 @@
+use std::path::{Path, PathBuf};
+
+use sha2::{Digest, Sha256};
+
+use crate::error::Result;
+use crate::vendor::VENDOR_METADATA;
+
+/// Files and directories cbuild itself creates at the root of
+/// dependencies, which don't count towards their contents. Entries of the
+/// same name further down are part of the dependency.
+const GENERATED: &[&str] = &[".git", ".cbuild-ready", VENDOR_METADATA];
+
+pub fn is_generated(file_name: &std::ffi::OsStr) -> bool {
+    GENERATED.iter().any(|generated| file_name == *generated)
+}
+
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// SHA-256 of the file at `path` as a hex string
+pub fn sha256_file(path: &Path) -> Result<String> {
+    let contents = std::fs::read(path)?;
+    Ok(hex(&Sha256::digest(contents)))
+}
+
+/// SHA-256 over the relative paths and contents of every file below `dir`,
+/// in a fixed order so equal trees always hash the same
+pub fn sha256_dir(dir: &Path) -> Result<String> {
+    sha256_dir_except(dir, &[])
+}
+
+/// `sha256_dir` leaving out the files `skip`, given relative to `dir`
+pub fn sha256_dir_except(dir: &Path, skip: &[PathBuf]) -> Result<String> {
+    let mut files = Vec::new();
+    let mut directories = vec![dir.to_path_buf()];
+    while let Some(current) = directories.pop() {
+        for entry in std::fs::read_dir(&current)? {
+            let path = entry?.path();
+            if current == dir && path.file_name().is_some_and(is_generated) {
+                continue;
+            }
+            if path.is_dir() {
+                directories.push(path);
+            }
+            else if !skip.iter().any(|skipped| path.strip_prefix(dir).is_ok_and(|relative| relative == skipped)) {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+
+    let mut hasher = Sha256::new();
+    for file in files {
+        let relative = file.strip_prefix(dir).unwrap_or(&file);
+        hasher.update(relative.to_string_lossy().replace('\\', "/").as_bytes());
+        hasher.update([0]);
+        hasher.update(sha256_file(&file)?.as_bytes());
+    }
+
+    Ok(hex(&hasher.finalize()))
+}
@@ -0,0 +1,453 @@
+use crate::diagnostic::closest_match;
+use crate::error::{Error, Result};
+
+/// A flag or option accepted on the command line
+pub struct Opt {
+    pub long: &'static str,
+    pub short: Option<char>,
+
+    /// Placeholder shown in help for options taking a value, `None` for
+    /// plain flags
+    pub value: Option<&'static str>,
+
+    pub help: &'static str,
+}
+
+/// A subcommand and everything it accepts
+pub struct Command {
+    pub name: &'static str,
+    pub about: &'static str,
+
+    /// Positional arguments as shown in the usage line, e.g. `[FILE]`
+    pub positionals: &'static [&'static str],
+
+    /// Whether arguments after `--` are accepted and passed through
+    pub passthrough: bool,
+
+    pub options: &'static [&'static [Opt]],
+}
+
+/// Options accepted by every command
+const GLOBAL_OPTIONS: &[Opt] = &[
+    Opt { long: "verbose", short: Some('v'), value: None, help: "Enable verbose output" },
+    Opt { long: "help", short: Some('h'), value: None, help: "Print help for the command" },
+    Opt { long: "offline", short: None, value: None, help: "Fail instead of accessing the network" },
+];
+
+/// Options of commands that read the project manifest
+const MANIFEST_OPTIONS: &[Opt] = &[
+    Opt { long: "manifest-path", short: None, value: Some("PATH"), help: "Path to the project's cbuild.toml or config.toml" },
+];
+
+/// Options of commands that can apply to a single workspace member
+const PACKAGE_OPTIONS: &[Opt] = &[
+    Opt { long: "package", short: Some('p'), value: Some("NAME"), help: "Workspace package to operate on" },
+];
+
+/// Options overriding `[settings]` and selecting the profile
+const SETTINGS_OPTIONS: &[Opt] = &[
+    Opt { long: "compiler", short: None, value: Some("NAME"), help: "Compiler to use (gcc, clang, msvc)" },
+    Opt { long: "standard", short: None, value: Some("STD"), help: "C or C++ standard to use, e.g. c11 or cpp17" },
+    Opt { long: "target", short: None, value: Some("TARGET"), help: "Target architecture (x86_64)" },
+    Opt { long: "mode", short: None, value: Some("MODE"), help: "Build mode (debug, release)" },
+    Opt { long: "release", short: None, value: None, help: "Build with the release profile" },
+    Opt { long: "profile", short: None, value: Some("NAME"), help: "Build with the given profile" },
+];
+
+/// Options controlling compilation
+const BUILD_OPTIONS: &[Opt] = &[
+    Opt { long: "jobs", short: Some('j'), value: Some("N"), help: "Number of parallel compiler jobs (default: all CPUs)" },
+    Opt { long: "keep-going", short: None, value: None, help: "Keep compiling after a source file fails" },
+];
+
+const TEST_OPTIONS: &[Opt] = &[
+    Opt { long: "no-run", short: None, value: None, help: "Build the tests without running them" },
+];
+
+const ADD_OPTIONS: &[Opt] = &[
+    Opt { long: "path", short: None, value: Some("PATH"), help: "Add a dependency on the project in PATH" },
+    Opt { long: "git", short: None, value: Some("URL"), help: "Add a dependency on a git repository" },
+    Opt { long: "branch", short: None, value: Some("BRANCH"), help: "Git branch to follow" },
+    Opt { long: "tag", short: None, value: Some("TAG"), help: "Git tag to use" },
+    Opt { long: "rev", short: None, value: Some("REV"), help: "Git commit to use" },
+];
+
+const TREE_OPTIONS: &[Opt] = &[
+    Opt { long: "invert", short: Some('i'), value: Some("NAME"), help: "Show the packages depending on NAME instead" },
+    Opt { long: "format", short: None, value: Some("FORMAT"), help: "Output format: text, dot or json" },
+];
+
+pub const COMMANDS: &[Command] = &[
+    Command {
+        name: "new",
+        about: "Create a new project, or a source file when NAME ends in .c/.cpp",
+        positionals: &["<NAME>"],
+        passthrough: false,
+        options: &[GLOBAL_OPTIONS],
+    },
+    Command {
+        name: "build",
+        about: "Build the project",
+        positionals: &[],
+        passthrough: false,
+        options: &[GLOBAL_OPTIONS, MANIFEST_OPTIONS, PACKAGE_OPTIONS, SETTINGS_OPTIONS, BUILD_OPTIONS],
+    },
+    Command {
+        name: "run",
+        about: "Build and run the project or a specific file",
+        positionals: &["[FILE]"],
+        passthrough: true,
+        options: &[GLOBAL_OPTIONS, MANIFEST_OPTIONS, PACKAGE_OPTIONS, SETTINGS_OPTIONS, BUILD_OPTIONS],
+    },
+    Command {
+        name: "test",
+        about: "Build and run the tests in tests/",
+        positionals: &["[FILTER]"],
+        passthrough: false,
+        options: &[GLOBAL_OPTIONS, MANIFEST_OPTIONS, PACKAGE_OPTIONS, SETTINGS_OPTIONS, BUILD_OPTIONS, TEST_OPTIONS],
+    },
+    Command {
+        name: "compdb",
+        about: "Write compile_commands.json without building",
+        positionals: &[],
+        passthrough: false,
+        options: &[GLOBAL_OPTIONS, MANIFEST_OPTIONS, PACKAGE_OPTIONS, SETTINGS_OPTIONS],
+    },
+    Command {
+        name: "update",
+        about: "Resolve dependencies again and update cbuild.lock",
+        positionals: &["[NAME]"],
+        passthrough: false,
+        options: &[GLOBAL_OPTIONS, MANIFEST_OPTIONS],
+    },
+    Command {
+        name: "tree",
+        about: "Print the dependency graph",
+        positionals: &[],
+        passthrough: false,
+        options: &[GLOBAL_OPTIONS, MANIFEST_OPTIONS, PACKAGE_OPTIONS, TREE_OPTIONS],
+    },
+    Command {
+        name: "add",
+        about: "Add a dependency to the manifest, NAME@VERSION for registry packages",
+        positionals: &["<NAME[@VERSION]>"],
+        passthrough: false,
+        options: &[GLOBAL_OPTIONS, MANIFEST_OPTIONS, PACKAGE_OPTIONS, ADD_OPTIONS],
+    },
+    Command {
+        name: "remove",
+        about: "Remove a dependency from the manifest",
+        positionals: &["<NAME>"],
+        passthrough: false,
+        options: &[GLOBAL_OPTIONS, MANIFEST_OPTIONS, PACKAGE_OPTIONS],
+    },
+    Command {
+        name: "vendor",
+        about: "Copy all dependencies into vendor/ and build from there",
+        positionals: &[],
+        passthrough: false,
+        options: &[GLOBAL_OPTIONS, MANIFEST_OPTIONS],
+    },
+    Command {
+        name: "package",
+        about: "Create a registry archive of the project in bin/package/",
+        positionals: &[],
+        passthrough: false,
+        options: &[GLOBAL_OPTIONS, MANIFEST_OPTIONS, PACKAGE_OPTIONS],
+    },
+    Command {
+        name: "publish",
+        about: "Package the project and add it to the registry",
+        positionals: &[],
+        passthrough: false,
+        options: &[GLOBAL_OPTIONS, MANIFEST_OPTIONS, PACKAGE_OPTIONS],
+    },
+    Command {
+        name: "clean",
+        about: "Remove build artifacts",
+        positionals: &[],
+        passthrough: false,
+        options: &[GLOBAL_OPTIONS, MANIFEST_OPTIONS],
+    },
+    Command {
+        name: "version",
+        about: "Print version info",
+        positionals: &[],
+        passthrough: false,
+        options: &[GLOBAL_OPTIONS],
+    },
+    Command {
+        name: "help",
+        about: "Print this help message, or the help of a command",
+        positionals: &["[COMMAND]"],
+        passthrough: false,
+        options: &[GLOBAL_OPTIONS],
+    },
+];
+
+/// Result of parsing the command line against `COMMANDS`
+#[derive(Clone, Debug, Default)]
+pub struct Matches {
+    pub command: String,
+    pub positionals: Vec<String>,
+
+    /// Arguments following `--`
+    pub passthrough: Vec<String>,
+
+    flags: Vec<&'static str>,
+    values: Vec<(&'static str, String)>,
+}
+
+impl Matches {
+    /// Whether flag `long` was given
+    pub fn flag(&self, long: &str) -> bool {
+        self.flags.contains(&long)
+    }
+
+    /// Value of option `long`, the last one wins when given repeatedly
+    pub fn value(&self, long: &str) -> Option<&str> {
+        self.values.iter().rev()
+            .find(|(name, _)| *name == long)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn positional(&self, index: usize) -> Option<&str> {
+        self.positionals.get(index).map(String::as_str)
+    }
+}
+
+pub fn find_command(name: &str) -> Option<&'static Command> {
+    COMMANDS.iter().find(|command| command.name == name)
+}
+
+/// Parses `args`, not including the program name
+pub fn parse(args: &[String]) -> Result<Matches> {
+    // Global flags may also come before the command, as in
+    // `cbuild --offline build`
+    let leading: Vec<&'static str> = args.iter()
+        .map_while(|arg| GLOBAL_OPTIONS.iter().find(|opt| {
+            opt.value.is_none() && opt.long != "help"
+                && (arg.strip_prefix("--") == Some(opt.long) || opt.short.is_some_and(|short| *arg == format!("-{}", short)))
+        }))
+        .map(|opt| opt.long)
+        .collect();
+    let args = &args[leading.len()..];
+
+    let Some(first) = args.first() else {
+        return Err(Error::Arguments("Not enough arguments".to_string()));
+    };
+
+    let name = match first.as_str() {
+        "-h" | "--help" => "help",
+        "-V" | "--version" => "version",
+        name => name,
+    };
+    let command = find_command(name).ok_or_else(|| {
+        let hint = closest_match(name, COMMANDS.iter().map(|command| command.name))
+            .map(|candidate| format!(", did you mean `{}`?", candidate))
+            .unwrap_or_default();
+        Error::Arguments(format!("Unknown command `{}`{}", name, hint))
+    })?;
+
+    let mut matches = Matches {
+        command: command.name.to_string(),
+        flags: leading,
+        ..Matches::default()
+    };
+
+    let mut rest = args[1..].iter();
+    while let Some(arg) = rest.next() {
+        if arg == "--" {
+            if !command.passthrough {
+                return Err(Error::Arguments(format!("`{}` does not accept arguments after `--`", command.name)));
+            }
+            matches.passthrough = rest.cloned().collect();
+            break;
+        }
+        else if let Some(long) = arg.strip_prefix("--") {
+            let (name, inline_value) = match long.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_string())),
+                None => (long, None),
+            };
+            let opt = find_long(command, name)?;
+            match (opt.value, inline_value) {
+                (None, None) => matches.flags.push(opt.long),
+                (None, Some(_)) => {
+                    return Err(Error::Arguments(format!("`--{}` does not take a value", opt.long)));
+                },
+                (Some(_), Some(value)) => matches.values.push((opt.long, value)),
+                (Some(_), None) => matches.values.push((opt.long, next_value(&mut rest, opt)?)),
+            }
+        }
+        else if let Some(shorts) = arg.strip_prefix('-').filter(|shorts| !shorts.is_empty()) {
+            // Clustered flags like `-vj4`, where a value runs to the end
+            for (i, short) in shorts.char_indices() {
+                let opt = find_short(command, short)?;
+                if opt.value.is_none() {
+                    matches.flags.push(opt.long);
+                    continue;
+                }
+
+                let attached = &shorts[i + short.len_utf8()..];
+                let value = if attached.is_empty() {
+                    next_value(&mut rest, opt)?
+                }
+                else {
+                    attached.to_string()
+                };
+                matches.values.push((opt.long, value));
+                break;
+            }
+        }
+        else {
+            if matches.positionals.len() >= command.positionals.len() {
+                return Err(Error::Arguments(format!("Unexpected argument `{}` for `{}`", arg, command.name)));
+            }
+            matches.positionals.push(arg.clone());
+        }
+    }
+
+    Ok(matches)
+}
+
+fn options(command: &Command) -> impl Iterator<Item = &'static Opt> + '_ {
+    command.options.iter().flat_map(|group| group.iter())
+}
+
+fn find_long(command: &Command, name: &str) -> Result<&'static Opt> {
+    options(command).find(|opt| opt.long == name).ok_or_else(|| {
+        let hint = closest_match(name, options(command).map(|opt| opt.long))
+            .map(|candidate| format!(", did you mean `--{}`?", candidate))
+            .unwrap_or_default();
+        Error::Arguments(format!("Unknown option `--{}` for `{}`{}", name, command.name, hint))
+    })
+}
+
+fn find_short(command: &Command, short: char) -> Result<&'static Opt> {
+    options(command).find(|opt| opt.short == Some(short)).ok_or_else(|| {
+        Error::Arguments(format!("Unknown option `-{}` for `{}`", short, command.name))
+    })
+}
+
+fn next_value<'a>(rest: &mut impl Iterator<Item = &'a String>, opt: &Opt) -> Result<String> {
+    rest.next().cloned().ok_or_else(|| {
+        Error::Arguments(format!("Missing value for `--{}`", opt.long))
+    })
+}
+
+/// Prints the overview of all commands
+pub fn print_help() {
+    println!("Usage: cbuild <COMMAND> [OPTIONS]");
+    println!("\nCommands:");
+    for command in COMMANDS {
+        let name = format!("{} {}", command.name, command.positionals.join(" "));
+        println!("  {:<18}{}", name.trim_end(), command.about);
+    }
+    println!("\nRun `cbuild help <COMMAND>` or `cbuild <COMMAND> --help` for the options of a command.");
+}
+
+/// Prints the usage and options of `command`
+pub fn print_command_help(command: &Command) {
+    println!("{}\n", command.about);
+
+    let mut usage = format!("Usage: cbuild {} [OPTIONS]", command.name);
+    for positional in command.positionals {
+        usage.push(' ');
+        usage.push_str(positional);
+    }
+    if command.passthrough {
+        usage.push_str(" [-- ARGS...]");
+    }
+    println!("{}", usage);
+
+    println!("\nOptions:");
+    for opt in options(command) {
+        let mut spec = match opt.short {
+            Some(short) => format!("-{}, --{}", short, opt.long),
+            None => format!("    --{}", opt.long),
+        };
+        if let Some(value) = opt.value {
+            spec.push(' ');
+            spec.push_str(value);
+        }
+        println!("  {:<26}{}", spec, opt.help);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_args(args: &[&str]) -> Result<Matches> {
+        parse(&args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn parses_long_options() {
+        let matches = parse_args(&["build", "--release", "--jobs", "4", "--compiler=clang"]).unwrap();
+        assert_eq!(matches.command, "build");
+        assert!(matches.flag("release"));
+        assert_eq!(matches.value("jobs"), Some("4"));
+        assert_eq!(matches.value("compiler"), Some("clang"));
+    }
+
+    #[test]
+    fn parses_clustered_short_flags() {
+        let matches = parse_args(&["build", "-vj4"]).unwrap();
+        assert!(matches.flag("verbose"));
+        assert_eq!(matches.value("jobs"), Some("4"));
+
+        let matches = parse_args(&["build", "-vj", "8"]).unwrap();
+        assert!(matches.flag("verbose"));
+        assert_eq!(matches.value("jobs"), Some("8"));
+    }
+
+    #[test]
+    fn last_value_wins() {
+        let matches = parse_args(&["build", "-j2", "--jobs=6"]).unwrap();
+        assert_eq!(matches.value("jobs"), Some("6"));
+    }
+
+    #[test]
+    fn passes_arguments_after_double_dash_through() {
+        let matches = parse_args(&["run", "main.c", "--", "--verbose", "-x", "--"]).unwrap();
+        assert_eq!(matches.positionals, ["main.c"]);
+        assert_eq!(matches.passthrough, ["--verbose", "-x", "--"]);
+        assert!(!matches.flag("verbose"));
+    }
+
+    #[test]
+    fn rejects_double_dash_for_commands_without_passthrough() {
+        let error = parse_args(&["build", "--", "x"]).unwrap_err();
+        assert_eq!(error.to_string(), "Argument error: `build` does not accept arguments after `--`");
+    }
+
+    #[test]
+    fn accepts_global_flags_before_the_command() {
+        let matches = parse_args(&["--offline", "-v", "build"]).unwrap();
+        assert_eq!(matches.command, "build");
+        assert!(matches.flag("offline"));
+        assert!(matches.flag("verbose"));
+    }
+
+    #[test]
+    fn reports_unknown_options_and_missing_values() {
+        let error = parse_args(&["build", "--relase"]).unwrap_err();
+        assert_eq!(error.to_string(), "Argument error: Unknown option `--relase` for `build`, did you mean `--release`?");
+
+        let error = parse_args(&["build", "-vx"]).unwrap_err();
+        assert_eq!(error.to_string(), "Argument error: Unknown option `-x` for `build`");
+
+        let error = parse_args(&["build", "-j"]).unwrap_err();
+        assert_eq!(error.to_string(), "Argument error: Missing value for `--jobs`");
+
+        let error = parse_args(&["build", "--release=yes"]).unwrap_err();
+        assert_eq!(error.to_string(), "Argument error: `--release` does not take a value");
+    }
+
+    #[test]
+    fn rejects_extra_positionals() {
+        let error = parse_args(&["build", "main.c"]).unwrap_err();
+        assert_eq!(error.to_string(), "Argument error: Unexpected argument `main.c` for `build`");
+    }
+}
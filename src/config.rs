@@ -0,0 +1,786 @@
+use std::collections::BTreeMap;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::dependency::{parse_dependency, parse_system_dependency, Dependency, DependencySection, SystemDependency, SystemDependencySection};
+use crate::diagnostic::Diagnostic;
+use crate::error::{Error, Result};
+use crate::foreign::BuildSystem;
+use crate::profile::{resolve_profile, DebugInfo, OptLevel, Profile, ProfileSection};
+use crate::registry::DEFAULT_REGISTRY;
+
+/// Programming languages
+#[derive(Clone, Debug)]
+#[allow(clippy::upper_case_acronyms)]
+pub enum Language {
+    C,
+    CPP,
+}
+
+/// Programming language standards
+#[derive(Clone, Debug)]
+#[allow(clippy::upper_case_acronyms)]
+pub enum Standard {
+    C89,
+    C99,
+    C11,
+    C17,
+    CPP98,
+    CPP11,
+    CPP14,
+    CPP17,
+    CPP20,
+}
+
+/// Compilers
+#[derive(Clone, Debug)]
+#[allow(clippy::upper_case_acronyms)]
+pub enum Compiler {
+    /// GNU Compiler Collection (linux default)
+    GCC,
+
+    /// C/C++ LLVM Compiler
+    CLANG,
+
+    /// Microsoft Visual C++ (windows default)
+    MSVC,
+}
+
+/// Build type
+#[derive(Clone, Debug)]
+pub enum Type {
+    /// Standard binary executable (default)
+    Binary,
+
+    /// Static library archive (`lib<name>.a`, `<name>.lib` with MSVC)
+    Library,
+
+    /// Shared library (`lib<name>.so`, `<name>.dll` with MSVC)
+    DynLibrary,
+}
+
+/// Build target
+#[derive(Clone, Debug, PartialEq)]
+pub enum Target {
+    X86_64,
+}
+
+/// Build mode, selecting the `debug` or `release` profile by default
+#[derive(Clone, Debug)]
+pub enum Mode {
+    /// (default)
+    Debug,
+
+    /// Debug symbols removed and optimization enabled
+    Release,
+}
+
+impl Mode {
+    pub fn profile_name(&self) -> &'static str {
+        match self {
+            Mode::Debug => "debug",
+            Mode::Release => "release",
+        }
+    }
+}
+
+/// Settings spelled as one of a fixed set of strings in `config.toml`
+pub trait ConfigValue: Sized + Clone + 'static {
+    /// Accepted spellings, matched case-insensitively
+    const VALUES: &'static [(&'static str, Self)];
+
+    fn from_config(value: &str) -> Option<Self> {
+        Self::VALUES.iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(value))
+            .map(|(_, variant)| variant.clone())
+    }
+
+    fn names() -> Vec<&'static str> {
+        Self::VALUES.iter().map(|(name, _)| *name).collect()
+    }
+}
+
+impl ConfigValue for Language {
+    const VALUES: &'static [(&'static str, Self)] = &[
+        ("c", Language::C),
+        ("cpp", Language::CPP),
+    ];
+}
+
+impl ConfigValue for Standard {
+    const VALUES: &'static [(&'static str, Self)] = &[
+        ("c89", Standard::C89),
+        ("c99", Standard::C99),
+        ("c11", Standard::C11),
+        ("c17", Standard::C17),
+        ("cpp98", Standard::CPP98),
+        ("cpp11", Standard::CPP11),
+        ("cpp14", Standard::CPP14),
+        ("cpp17", Standard::CPP17),
+        ("cpp20", Standard::CPP20),
+    ];
+}
+
+impl Standard {
+    pub fn is_cpp(&self) -> bool {
+        matches!(self, Standard::CPP98 | Standard::CPP11 | Standard::CPP14 | Standard::CPP17 | Standard::CPP20)
+    }
+
+    fn c_names() -> Vec<&'static str> {
+        Self::VALUES.iter().filter(|(_, standard)| !standard.is_cpp()).map(|(name, _)| *name).collect()
+    }
+
+    fn cpp_names() -> Vec<&'static str> {
+        Self::VALUES.iter().filter(|(_, standard)| standard.is_cpp()).map(|(name, _)| *name).collect()
+    }
+}
+
+impl ConfigValue for Compiler {
+    const VALUES: &'static [(&'static str, Self)] = &[
+        ("gcc", Compiler::GCC),
+        ("clang", Compiler::CLANG),
+        ("msvc", Compiler::MSVC),
+    ];
+}
+
+impl ConfigValue for Type {
+    const VALUES: &'static [(&'static str, Self)] = &[
+        ("bin", Type::Binary),
+        ("lib", Type::Library),
+        ("dylib", Type::DynLibrary),
+    ];
+}
+
+impl ConfigValue for Target {
+    const VALUES: &'static [(&'static str, Self)] = &[
+        ("x86_64", Target::X86_64),
+    ];
+}
+
+impl ConfigValue for Mode {
+    const VALUES: &'static [(&'static str, Self)] = &[
+        ("debug", Mode::Debug),
+        ("release", Mode::Release),
+    ];
+}
+
+#[derive(Clone, Debug)]
+pub struct Settings {
+    pub language: Language,
+
+    /// Standard used for C sources
+    pub c_standard: Standard,
+
+    /// Standard used for C++ sources
+    pub cpp_standard: Standard,
+    pub compiler: Compiler,
+    pub build_type: Type,
+    pub target: Target,
+    pub mode: Mode,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            language: Language::C,
+            c_standard: Standard::C89,
+            cpp_standard: Standard::CPP17,
+            compiler: Compiler::GCC,
+            build_type: Type::Binary,
+            target: Target::X86_64,
+            mode: Mode::Debug,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub project_name: Option<String>,
+    pub project_version: Option<String>,
+
+    /// SPDX license expression, required to publish the project
+    pub project_license: Option<String>,
+
+    pub settings: Settings,
+    pub libraries: Vec<String>,
+
+    /// System libraries to link, such as `m` for `-lm`, which are also
+    /// linked into everything depending on the project
+    pub link_libraries: Vec<String>,
+
+    /// Glob patterns, relative to the project root, selecting source files
+    pub sources: Vec<String>,
+
+    /// Glob patterns removing files from `sources`
+    pub exclude: Vec<String>,
+
+    pub verbose: bool,
+
+    /// Maximum number of concurrent compiler processes, all CPUs if unset
+    pub jobs: Option<usize>,
+
+    /// Keep compiling other sources after one of them failed
+    pub keep_going: bool,
+
+    /// Profile the build uses
+    pub profile: Profile,
+
+    /// `[profile.<name>]` tables, kept to resolve `--profile` later
+    pub profiles: BTreeMap<String, ProfileSection>,
+
+    /// Manifest the config was loaded from, which may have another name
+    /// when given with `--manifest-path`
+    pub manifest: Option<PathBuf>,
+
+    /// Directory of the manifest, which `src/`, `lib/` and `bin/` are
+    /// relative to
+    pub root: PathBuf,
+
+    /// Directory holding the `<profile>/` output trees, shared by all
+    /// packages of a workspace
+    pub target_dir: PathBuf,
+
+    /// `[workspace]` member directories, as glob patterns relative to the
+    /// root
+    pub members: Vec<String>,
+
+    pub dependencies: Vec<Dependency>,
+
+    /// Libraries found through pkg-config
+    pub system_dependencies: Vec<SystemDependency>,
+
+    /// Package registry version requirements are resolved from, a
+    /// directory or an `http://` URL
+    pub registry: String,
+
+    /// Directory `cbuild vendor` copied the dependencies to, which they are
+    /// then exclusively resolved from
+    pub vendor: Option<PathBuf>,
+
+    /// Fail instead of reaching anything outside the local filesystem
+    pub offline: bool,
+
+    /// Standard given with `--standard`, which every workspace member is
+    /// built with
+    pub standard_override: Option<Standard>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            project_name: None,
+            project_version: None,
+            project_license: None,
+            settings: Settings::default(),
+            libraries: Vec::new(),
+            link_libraries: Vec::new(),
+            sources: vec![DEFAULT_SOURCES.to_string()],
+            exclude: Vec::new(),
+            verbose: false,
+            jobs: None,
+            keep_going: false,
+            profile: Profile::default(),
+            profiles: BTreeMap::new(),
+            manifest: None,
+            root: PathBuf::from("."),
+            target_dir: PathBuf::from("bin"),
+            members: Vec::new(),
+            dependencies: Vec::new(),
+            system_dependencies: Vec::new(),
+            registry: DEFAULT_REGISTRY.to_string(),
+            vendor: None,
+            offline: false,
+            standard_override: None,
+        }
+    }
+}
+
+impl Config {
+    pub fn new(project_name: &str) -> Self {
+        Config {
+            project_name: Some(project_name.to_string()),
+            ..Config::default()
+        }
+    }
+
+    /// Loads the manifest of the working directory or the closest of its
+    /// ancestors that has one
+    pub fn load() -> Result<Self> {
+        let working_directory = std::env::current_dir()?;
+        let config_file = Self::find_config_file(&working_directory)?;
+        Self::load_from(&config_file)
+    }
+
+    /// Loads the config from `config_file` instead of searching for it
+    pub fn load_from(config_file: &Path) -> Result<Self> {
+        if !config_file.is_file() {
+            return Err(Error::Config(format!("Manifest not found at {}", config_file.display())));
+        }
+
+        let config_file = config_file.canonicalize()?;
+        let contents = std::fs::read_to_string(&config_file)?;
+        let mut config = parse_config_toml(&contents, &config_file)?;
+        config.root = config_file.parent().unwrap().to_path_buf();
+        config.manifest = Some(config_file.clone());
+        config.target_dir = config.root.join("bin");
+        config.vendor = config.vendor.map(|vendor| config.root.join(vendor));
+
+        if let Ok(registry) = std::env::var("CBUILD_REGISTRY") {
+            config.registry = registry;
+        }
+        else if !is_url(&config.registry) {
+            // Relative registry paths in the manifest are relative to it
+            let registry = PathBuf::from(shellexpand::tilde(&config.registry).to_string());
+            let registry = config.root.join(registry);
+            config.registry = registry.canonicalize().unwrap_or(registry).display().to_string();
+        }
+
+        if let Ok(jobs) = std::env::var("CBUILD_JOBS") {
+            config.jobs = Some(parse_jobs(&jobs).ok_or_else(|| {
+                Error::Config(format!("Invalid CBUILD_JOBS value `{}`", jobs))
+            })?);
+        }
+
+        Ok(config)
+    }
+
+    /// Uses `standard` for the sources of its language
+    pub fn override_standard(&mut self, standard: Standard) {
+        if standard.is_cpp() {
+            self.settings.cpp_standard = standard.clone();
+        }
+        else {
+            self.settings.c_standard = standard.clone();
+        }
+        self.standard_override = Some(standard);
+    }
+
+    /// Switches the build to profile `name`
+    pub fn select_profile(&mut self, name: &str) -> Result<()> {
+        self.profile = resolve_profile(&self.profiles, name)?;
+        Ok(())
+    }
+
+    fn find_config_file(path: &Path) -> Result<PathBuf> {
+        path.ancestors()
+            .find_map(manifest_in)
+            .ok_or(Error::NoConfig())
+    }
+}
+
+/// Manifest of the project in `dir`, if it has one
+pub fn manifest_in(dir: &Path) -> Option<PathBuf> {
+    MANIFEST_NAMES.iter()
+        .map(|name| dir.join(name))
+        .find(|candidate| candidate.is_file())
+}
+
+/// File names of the manifest, in order of preference when a directory
+/// has several
+pub const MANIFEST_NAMES: &[&str] = &["cbuild.toml", "config.toml"];
+
+/// Sources compiled when the config doesn't list any
+pub const DEFAULT_SOURCES: &str = "src/**";
+
+/// On-disk layout of `config.toml`
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct Manifest {
+    project: ProjectSection,
+    settings: SettingsSection,
+    profile: BTreeMap<String, ProfileSection>,
+    workspace: WorkspaceSection,
+    dependencies: BTreeMap<String, DependencySection>,
+    #[serde(rename = "system-dependencies")]
+    system_dependencies: BTreeMap<String, SystemDependencySection>,
+    registry: RegistrySection,
+    vendor: VendorSection,
+}
+
+/// `[project]` table
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct ProjectSection {
+    name: Option<String>,
+    version: Option<String>,
+    license: Option<String>,
+}
+
+/// `[workspace]` table
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct WorkspaceSection {
+    members: Vec<String>,
+}
+
+/// `[registry]` table
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct RegistrySection {
+    url: Option<String>,
+}
+
+/// `[vendor]` table
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct VendorSection {
+    directory: Option<String>,
+}
+
+/// `[settings]` table
+///
+/// Enum-like values are kept as strings here and converted afterwards, so
+/// that an unsupported value can be reported together with its key.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct SettingsSection {
+    language: Option<String>,
+    standard: Option<String>,
+    c_standard: Option<String>,
+    cpp_standard: Option<String>,
+    compiler: Option<String>,
+    #[serde(rename = "type")]
+    build_type: Option<String>,
+    target: Option<String>,
+    mode: Option<String>,
+    libraries: Vec<String>,
+    link_libraries: Vec<String>,
+    sources: Option<Vec<String>>,
+    exclude: Vec<String>,
+    jobs: Option<usize>,
+}
+
+/// Expected shape of a value in `config.toml`
+enum Kind {
+    String,
+    Bool,
+    StringArray,
+    GlobArray,
+    PositiveInteger,
+
+    /// String, integer or boolean spelled as one of the given names
+    OneOf(fn() -> Vec<&'static str>),
+
+    /// Table with a fixed set of keys
+    Table(&'static [(&'static str, Kind)]),
+
+    /// Table with arbitrary keys whose values all have the same kind
+    Map(&'static Kind),
+
+    /// String, or a table with a fixed set of keys
+    StringOrTable(&'static [(&'static str, Kind)]),
+}
+
+/// Every table and key understood by `parse_config_toml`
+const SCHEMA: Kind = Kind::Table(&[
+    ("project", Kind::Table(&[
+        ("name", Kind::String),
+        ("version", Kind::String),
+        ("license", Kind::String),
+    ])),
+    ("settings", Kind::Table(&[
+        ("language", Kind::OneOf(Language::names)),
+        ("standard", Kind::OneOf(Standard::names)),
+        ("c_standard", Kind::OneOf(Standard::c_names)),
+        ("cpp_standard", Kind::OneOf(Standard::cpp_names)),
+        ("compiler", Kind::OneOf(Compiler::names)),
+        ("type", Kind::OneOf(Type::names)),
+        ("target", Kind::OneOf(Target::names)),
+        ("mode", Kind::OneOf(Mode::names)),
+        ("libraries", Kind::StringArray),
+        ("link_libraries", Kind::StringArray),
+        ("sources", Kind::GlobArray),
+        ("exclude", Kind::GlobArray),
+        ("jobs", Kind::PositiveInteger),
+    ])),
+    ("profile", Kind::Map(&Kind::Table(&[
+        ("inherits", Kind::String),
+        ("opt-level", Kind::OneOf(OptLevel::names)),
+        ("debug", Kind::OneOf(DebugInfo::names)),
+        ("strip", Kind::Bool),
+        ("assertions", Kind::Bool),
+        ("flags", Kind::StringArray),
+        ("link-flags", Kind::StringArray),
+    ]))),
+    ("workspace", Kind::Table(&[
+        ("members", Kind::GlobArray),
+    ])),
+    ("dependencies", Kind::Map(&Kind::StringOrTable(&[
+        ("version", Kind::String),
+        ("path", Kind::String),
+        ("git", Kind::String),
+        ("branch", Kind::String),
+        ("tag", Kind::String),
+        ("rev", Kind::String),
+        ("archive", Kind::String),
+        ("sha256", Kind::String),
+        ("build", Kind::OneOf(BuildSystem::names)),
+        ("options", Kind::Map(&Kind::String)),
+    ]))),
+    ("system-dependencies", Kind::Map(&Kind::StringOrTable(&[
+        ("version", Kind::String),
+        ("static", Kind::Bool),
+    ]))),
+    ("registry", Kind::Table(&[
+        ("url", Kind::String),
+    ])),
+    ("vendor", Kind::Table(&[
+        ("directory", Kind::String),
+    ])),
+]);
+
+pub fn parse_config_toml(config: &str, path: &Path) -> Result<Config> {
+    validate_config_toml(config, path)?;
+
+    let manifest: Manifest = toml::from_str(config)
+        .map_err(|e| Error::Config(e.to_string()))?;
+
+    let mut settings = Settings::default();
+    let section = manifest.settings;
+
+    if let Some(value) = &section.language {
+        settings.language = parse_value("settings.language", value)?;
+    }
+    // `standard` predates mixed projects and applies to its own language
+    if let Some(value) = &section.standard {
+        let standard: Standard = parse_value("settings.standard", value)?;
+        if standard.is_cpp() {
+            settings.cpp_standard = standard;
+        }
+        else {
+            settings.c_standard = standard;
+        }
+    }
+    if let Some(value) = &section.c_standard {
+        settings.c_standard = parse_value("settings.c_standard", value)?;
+        if settings.c_standard.is_cpp() {
+            return Err(Error::Config(format!("`{}` is not a C standard (settings.c_standard)", value)));
+        }
+    }
+    if let Some(value) = &section.cpp_standard {
+        settings.cpp_standard = parse_value("settings.cpp_standard", value)?;
+        if !settings.cpp_standard.is_cpp() {
+            return Err(Error::Config(format!("`{}` is not a C++ standard (settings.cpp_standard)", value)));
+        }
+    }
+    if let Some(value) = &section.compiler {
+        settings.compiler = parse_value("settings.compiler", value)?;
+    }
+    if let Some(value) = &section.build_type {
+        settings.build_type = parse_value("settings.type", value)?;
+    }
+    if let Some(value) = &section.target {
+        settings.target = parse_value("settings.target", value)?;
+    }
+    if let Some(value) = &section.mode {
+        settings.mode = parse_value("settings.mode", value)?;
+    }
+
+    // Resolve every declared profile so mistakes surface even in profiles
+    // that aren't selected
+    for name in manifest.profile.keys() {
+        resolve_profile(&manifest.profile, name)?;
+    }
+    let profile = resolve_profile(&manifest.profile, settings.mode.profile_name())?;
+
+    let dependencies = manifest.dependencies.iter()
+        .map(|(name, section)| parse_dependency(name, section))
+        .collect::<Result<Vec<_>>>()?;
+    let system_dependencies = manifest.system_dependencies.iter()
+        .map(|(name, section)| parse_system_dependency(name, section))
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(Config {
+        project_name: manifest.project.name,
+        project_version: manifest.project.version,
+        project_license: manifest.project.license,
+        settings,
+        libraries: section.libraries,
+        link_libraries: section.link_libraries,
+        sources: section.sources.unwrap_or_else(|| vec![DEFAULT_SOURCES.to_string()]),
+        exclude: section.exclude,
+        jobs: section.jobs,
+        profile,
+        profiles: manifest.profile,
+        members: manifest.workspace.members,
+        dependencies,
+        system_dependencies,
+        registry: manifest.registry.url.unwrap_or_else(|| DEFAULT_REGISTRY.to_string()),
+        vendor: manifest.vendor.directory.map(PathBuf::from),
+        ..Config::default()
+    })
+}
+
+/// Fails in offline mode, as `url` is not on the local filesystem
+pub fn require_network(config: &Config, url: &str) -> Result<()> {
+    if config.offline {
+        return Err(Error::Library(format!(
+            "Cannot access {} in offline mode, vendor the dependencies with `cbuild vendor` first", url)));
+    }
+    Ok(())
+}
+
+/// Whether a registry location is a URL rather than a directory
+pub fn is_url(location: &str) -> bool {
+    location.starts_with("http://") || location.starts_with("https://")
+}
+
+/// Parses a job count, which must be at least one
+pub fn parse_jobs(value: &str) -> Option<usize> {
+    value.trim().parse().ok().filter(|jobs| *jobs > 0)
+}
+
+fn parse_value<T: ConfigValue>(key: &str, value: &str) -> Result<T> {
+    T::from_config(value).ok_or_else(|| {
+        Error::Config(format!("Unsupported value `{}` for key `{}`", value, key))
+    })
+}
+
+/// Checks `config` against `SCHEMA`, reporting every unknown key and
+/// invalid value with its location
+fn validate_config_toml(config: &str, path: &Path) -> Result<()> {
+    let document = toml_edit::ImDocument::parse(config).map_err(|e| {
+        let span = e.span().unwrap_or(0..0);
+        Error::Diagnostics(vec![Diagnostic::new(path, config, span, e.message().trim())])
+    })?;
+
+    let mut validator = Validator { source: config, path, diagnostics: Vec::new() };
+    let Kind::Table(keys) = &SCHEMA else { unreachable!() };
+    validator.table(document.as_table(), keys, "");
+
+    if validator.diagnostics.is_empty() {
+        Ok(())
+    }
+    else {
+        Err(Error::Diagnostics(validator.diagnostics))
+    }
+}
+
+/// Collects diagnostics while walking a document along `SCHEMA`
+struct Validator<'a> {
+    source: &'a str,
+    path: &'a Path,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Validator<'_> {
+    fn error(&self, span: Range<usize>, message: String) -> Diagnostic {
+        Diagnostic::new(self.path, self.source, span, message)
+    }
+
+    fn table(&mut self, table: &dyn toml_edit::TableLike, keys: &[(&str, Kind)], prefix: &str) {
+        for (key, item) in table.iter() {
+            let key_span = table.key(key).and_then(|k| k.span()).unwrap_or(0..0);
+            let full_key = format!("{}{}", prefix, key);
+
+            match keys.iter().find(|(name, _)| *name == key) {
+                Some((_, kind)) => self.item(item, kind, &full_key, key_span),
+                None => {
+                    let what = if prefix.is_empty() { "table" } else { "key" };
+                    self.diagnostics.push(self.error(key_span, format!("unknown {} `{}`", what, full_key))
+                        .suggest(key, keys.iter().map(|(name, _)| *name)));
+                },
+            }
+        }
+    }
+
+    fn item(&mut self, item: &toml_edit::Item, kind: &Kind, full_key: &str, key_span: Range<usize>) {
+        let span = item.span().unwrap_or(key_span.clone());
+        match kind {
+            Kind::String => {
+                if !item.is_str() {
+                    self.diagnostics.push(self.error(span, format!("`{}` must be a string", full_key)));
+                }
+            },
+            Kind::Bool => {
+                if !item.is_bool() {
+                    self.diagnostics.push(self.error(span, format!("`{}` must be `true` or `false`", full_key)));
+                }
+            },
+            Kind::StringArray => {
+                let valid = item.as_array().is_some_and(|array| array.iter().all(|v| v.is_str()));
+                if !valid {
+                    self.diagnostics.push(self.error(span, format!("`{}` must be an array of strings", full_key)));
+                }
+            },
+            Kind::GlobArray => match item.as_array() {
+                Some(array) => {
+                    for value in array.iter() {
+                        let span = value.span().unwrap_or(span.clone());
+                        match value.as_str().map(glob::Pattern::new) {
+                            Some(Ok(_)) => (),
+                            Some(Err(e)) => {
+                                self.diagnostics.push(self.error(span, format!("invalid glob pattern in `{}`: {}", full_key, e.msg)));
+                            },
+                            None => {
+                                self.diagnostics.push(self.error(span, format!("`{}` must be an array of strings", full_key)));
+                            },
+                        }
+                    }
+                },
+                None => {
+                    self.diagnostics.push(self.error(span, format!("`{}` must be an array of glob patterns", full_key)));
+                },
+            },
+            Kind::PositiveInteger => {
+                let valid = item.as_integer().is_some_and(|value| value > 0);
+                if !valid {
+                    self.diagnostics.push(self.error(span, format!("`{}` must be a positive integer", full_key)));
+                }
+            },
+            Kind::OneOf(names) => {
+                let names = names();
+                let accepted = names.iter()
+                    .map(|name| format!("`{}`", name))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+
+                let value = item.as_value().and_then(|value| match value {
+                    toml_edit::Value::String(s) => Some(s.value().clone()),
+                    toml_edit::Value::Integer(i) => Some(i.value().to_string()),
+                    toml_edit::Value::Boolean(b) => Some(b.value().to_string()),
+                    _ => None,
+                });
+
+                match value {
+                    Some(value) if names.iter().any(|name| name.eq_ignore_ascii_case(&value)) => (),
+                    Some(value) => {
+                        self.diagnostics.push(self.error(span, format!("unsupported value `{}` for `{}`", value, full_key))
+                            .note(format!("expected one of: {}", accepted))
+                            .suggest(&value, names.iter().copied()));
+                    },
+                    None => {
+                        self.diagnostics.push(self.error(span, format!("`{}` must be a string", full_key))
+                            .note(format!("expected one of: {}", accepted)));
+                    },
+                }
+            },
+            Kind::Table(keys) => match item.as_table_like() {
+                Some(table) => self.table(table, keys, &format!("{}.", full_key)),
+                None => {
+                    self.diagnostics.push(self.error(span, format!("`{}` must be a table", full_key)));
+                },
+            },
+            Kind::StringOrTable(keys) => match item.as_table_like() {
+                Some(table) => self.table(table, keys, &format!("{}.", full_key)),
+                None if item.is_str() => (),
+                None => {
+                    self.diagnostics.push(self.error(span, format!("`{}` must be a string or a table", full_key)));
+                },
+            },
+            Kind::Map(kind) => match item.as_table_like() {
+                Some(table) => {
+                    for (key, value) in table.iter() {
+                        let key_span = table.key(key).and_then(|k| k.span()).unwrap_or(0..0);
+                        self.item(value, kind, &format!("{}.{}", full_key, key), key_span);
+                    }
+                },
+                None => {
+                    self.diagnostics.push(self.error(span, format!("`{}` must be a table", full_key)));
+                },
+            },
+        }
+    }
+}
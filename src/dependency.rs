@@ -0,0 +1,262 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use semver::VersionReq;
+use serde::Deserialize;
+
+use crate::config::ConfigValue;
+use crate::error::{Error, Result};
+use crate::foreign::{BuildSystem, ForeignBuild};
+
+/// Where a dependency is taken from
+#[derive(Clone, Debug)]
+pub enum Source {
+    /// Another project on the local filesystem, relative to the depending
+    /// project
+    Path(PathBuf),
+
+    /// A git repository, checked out at the commit `reference` resolves to
+    Git {
+        url: String,
+        reference: GitReference,
+    },
+
+    /// The highest version in the registry matching the requirement
+    Registry(VersionReq),
+
+    /// A `.tar.gz` or `.tar` file, local or served over HTTP, whose contents
+    /// must have the SHA-256 `sha256`
+    Archive {
+        url: String,
+        sha256: String,
+    },
+}
+
+impl std::fmt::Display for Source {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Source::Path(path) => write!(f, "path+{}", path.display()),
+            Source::Git { url, reference: GitReference::DefaultBranch } => write!(f, "git+{}", url),
+            Source::Git { url, reference } => write!(f, "git+{}?{}", url, reference),
+            Source::Registry(requirement) => write!(f, "{}", requirement),
+            Source::Archive { url, .. } => write!(f, "archive+{}", url),
+        }
+    }
+}
+
+/// What a git dependency is pinned to
+#[derive(Clone, Debug)]
+pub enum GitReference {
+    /// `HEAD` of the repository
+    DefaultBranch,
+    Branch(String),
+    Tag(String),
+    Rev(String),
+}
+
+impl GitReference {
+    /// Revision understood by `git rev-parse`
+    pub fn rev_spec(&self) -> String {
+        match self {
+            GitReference::DefaultBranch => "HEAD".to_string(),
+            GitReference::Branch(branch) => format!("refs/heads/{}", branch),
+            GitReference::Tag(tag) => format!("refs/tags/{}", tag),
+            GitReference::Rev(rev) => rev.clone(),
+        }
+    }
+}
+
+impl std::fmt::Display for GitReference {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GitReference::DefaultBranch => write!(f, "HEAD"),
+            GitReference::Branch(branch) => write!(f, "branch={}", branch),
+            GitReference::Tag(tag) => write!(f, "tag={}", tag),
+            GitReference::Rev(rev) => write!(f, "rev={}", rev),
+        }
+    }
+}
+
+/// Entry of the `[dependencies]` table
+#[derive(Clone, Debug)]
+pub struct Dependency {
+    pub name: String,
+    pub source: Source,
+
+    /// Set for projects built by another build system instead of cbuild
+    pub build: Option<ForeignBuild>,
+}
+
+/// `[dependencies]` entry as written in the manifest, either a version
+/// requirement or a table
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum DependencySection {
+    Version(String),
+    Table(Box<DependencyTable>),
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct DependencyTable {
+    version: Option<String>,
+    path: Option<String>,
+    git: Option<String>,
+    branch: Option<String>,
+    tag: Option<String>,
+    rev: Option<String>,
+    archive: Option<String>,
+    sha256: Option<String>,
+    build: Option<String>,
+    options: Option<BTreeMap<String, String>>,
+}
+
+/// Entry of the `[system-dependencies]` table, a library found through
+/// pkg-config
+#[derive(Clone, Debug)]
+pub struct SystemDependency {
+    /// pkg-config package name
+    pub name: String,
+
+    pub requirement: VersionReq,
+
+    /// Link its static libraries instead of the shared ones
+    pub static_link: bool,
+}
+
+/// `[system-dependencies]` entry as written in the manifest, either a
+/// version requirement or a table
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum SystemDependencySection {
+    Version(String),
+    Table(SystemDependencyTable),
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct SystemDependencyTable {
+    version: Option<String>,
+    #[serde(rename = "static")]
+    static_link: bool,
+}
+
+pub fn parse_system_dependency(name: &str, section: &SystemDependencySection) -> Result<SystemDependency> {
+    let (version, static_link) = match section {
+        SystemDependencySection::Version(version) => (Some(version.as_str()), false),
+        SystemDependencySection::Table(table) => (table.version.as_deref(), table.static_link),
+    };
+
+    let requirement = match version {
+        Some(version) => VersionReq::parse(version).map_err(|e| {
+            Error::Config(format!("Invalid version requirement `{}` for system dependency `{}`: {}", version, name, e))
+        })?,
+        None => VersionReq::STAR,
+    };
+
+    Ok(SystemDependency {
+        name: name.to_string(),
+        requirement,
+        static_link,
+    })
+}
+
+pub fn parse_dependency(name: &str, section: &DependencySection) -> Result<Dependency> {
+    let section = match section {
+        DependencySection::Version(version) => &DependencyTable {
+            version: Some(version.clone()),
+            ..DependencyTable::default()
+        },
+        DependencySection::Table(table) => table,
+    };
+
+    let references: Vec<GitReference> = [
+        section.branch.clone().map(GitReference::Branch),
+        section.tag.clone().map(GitReference::Tag),
+        section.rev.clone().map(GitReference::Rev),
+    ].into_iter().flatten().collect();
+
+    if section.sha256.is_some() && section.archive.is_none() {
+        return Err(Error::Config(format!("`sha256` only applies to archive dependencies (dependencies.{})", name)));
+    }
+
+    let source = match (&section.path, &section.git) {
+        _ if section.archive.is_some() => {
+            if section.version.is_some() || section.path.is_some() || section.git.is_some() || !references.is_empty() {
+                return Err(Error::Config(format!(
+                    "`archive` cannot be combined with `version`, `path` or `git` (dependencies.{})", name)));
+            }
+            let sha256 = section.sha256.as_deref().ok_or_else(|| {
+                Error::Config(format!("Archive dependency `{}` needs a `sha256` checksum (dependencies.{})", name, name))
+            })?;
+            if sha256.len() != 64 || !sha256.chars().all(|c| c.is_ascii_hexdigit()) {
+                return Err(Error::Config(format!(
+                    "Invalid `sha256` `{}` for dependency `{}`, expected 64 hexadecimal digits", sha256, name)));
+            }
+            Source::Archive {
+                url: section.archive.clone().unwrap(),
+                sha256: sha256.to_lowercase(),
+            }
+        },
+        _ if section.version.is_some() => {
+            if section.path.is_some() || section.git.is_some() || !references.is_empty() {
+                return Err(Error::Config(format!(
+                    "`version` cannot be combined with `path` or `git` (dependencies.{})", name)));
+            }
+            let version = section.version.as_deref().unwrap();
+            Source::Registry(VersionReq::parse(version).map_err(|e| {
+                Error::Config(format!("Invalid version requirement `{}` for dependency `{}`: {}", version, name, e))
+            })?)
+        },
+        (Some(path), None) => {
+            if !references.is_empty() {
+                return Err(Error::Config(format!(
+                    "`branch`, `tag` and `rev` only apply to git dependencies (dependencies.{})", name)));
+            }
+            Source::Path(PathBuf::from(path))
+        },
+        (None, Some(url)) => {
+            if references.len() > 1 {
+                return Err(Error::Config(format!(
+                    "Only one of `branch`, `tag` and `rev` may be given (dependencies.{})", name)));
+            }
+            Source::Git {
+                url: url.clone(),
+                reference: references.into_iter().next().unwrap_or(GitReference::DefaultBranch),
+            }
+        },
+        (Some(_), Some(_)) => {
+            return Err(Error::Config(format!("Dependency `{}` has both a `path` and a `git` source", name)));
+        },
+        (None, None) => {
+            return Err(Error::Config(format!("Dependency `{}` needs a `version`, `path`, `git` or `archive` source (dependencies.{})", name, name)));
+        },
+    };
+
+    let build = match &section.build {
+        _ if matches!(source, Source::Registry(_)) => {
+            if section.build.is_some() || section.options.is_some() {
+                return Err(Error::Config(format!(
+                    "Registry dependencies are always cbuild projects, `build` and `options` need a `path`, `git` or `archive` source (dependencies.{})", name)));
+            }
+            None
+        },
+        Some(system) => Some(ForeignBuild {
+            system: BuildSystem::from_config(system).ok_or_else(|| {
+                Error::Config(format!("Unsupported build system `{}` for dependency `{}`, expected one of: {}",
+                    system, name, BuildSystem::names().join(", ")))
+            })?,
+            options: section.options.clone().unwrap_or_default(),
+        }),
+        None if section.options.is_some() => {
+            return Err(Error::Config(format!("`options` need a foreign `build` system (dependencies.{})", name)));
+        },
+        None => None,
+    };
+
+    Ok(Dependency {
+        name: name.to_string(),
+        source,
+        build,
+    })
+}
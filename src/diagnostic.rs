@@ -0,0 +1,143 @@
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+/// A problem located in a source file, rendered with a snippet of the
+/// offending line
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub file: PathBuf,
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+    source_line: String,
+    underline: Range<usize>,
+    notes: Vec<String>,
+}
+
+impl Diagnostic {
+    /// Creates a diagnostic for the byte range `span` of `source`
+    pub fn new(file: &Path, source: &str, span: Range<usize>, message: impl Into<String>) -> Self {
+        let start = span.start.min(source.len());
+        let end = span.end.clamp(start, source.len());
+
+        let line_start = source[..start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = source[start..].find('\n').map_or(source.len(), |i| start + i);
+        let source_line = source[line_start..line_end].trim_end_matches('\r').to_string();
+
+        let line = source[..start].matches('\n').count() + 1;
+        let column = source[line_start..start].chars().count() + 1;
+        let width = source[start..end.min(line_end)].chars().count().max(1);
+
+        Diagnostic {
+            file: file.to_path_buf(),
+            line,
+            column,
+            message: message.into(),
+            source_line,
+            underline: column - 1..column - 1 + width,
+            notes: Vec::new(),
+        }
+    }
+
+    /// Appends a `= note` line shown below the snippet
+    pub fn note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    /// Appends a "did you mean" hint when `candidates` has a close match
+    pub fn suggest<'a>(self, input: &str, candidates: impl IntoIterator<Item = &'a str>) -> Self {
+        match closest_match(input, candidates) {
+            Some(candidate) => self.note(format!("help: did you mean `{}`?", candidate)),
+            None => self,
+        }
+    }
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let gutter = " ".repeat(self.line.to_string().len());
+
+        writeln!(f, "{}:{}:{}: {}", self.file.display(), self.line, self.column, self.message)?;
+        writeln!(f, "{} |", gutter)?;
+        writeln!(f, "{} | {}", self.line, self.source_line)?;
+        write!(f, "{} | {}{}",
+            gutter,
+            " ".repeat(self.underline.start),
+            "^".repeat(self.underline.len()))?;
+        for note in &self.notes {
+            write!(f, "\n{} = {}", gutter, note)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns the candidate closest to `input`, if any is close enough to be
+/// a plausible typo
+pub fn closest_match<'a>(input: &str, candidates: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    let threshold = (input.chars().count() / 3).max(1);
+    candidates.into_iter()
+        .map(|candidate| (edit_distance(&input.to_lowercase(), &candidate.to_lowercase()), candidate))
+        .filter(|(distance, _)| *distance <= threshold)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// Levenshtein distance between two strings
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COMMANDS: &[&str] = &["build", "run", "test", "update", "publish"];
+
+    #[test]
+    fn suggests_the_closest_candidate() {
+        assert_eq!(closest_match("buld", COMMANDS.iter().copied()), Some("build"));
+        assert_eq!(closest_match("rum", COMMANDS.iter().copied()), Some("run"));
+        assert_eq!(closest_match("upadte", COMMANDS.iter().copied()), Some("update"));
+    }
+
+    #[test]
+    fn ignores_case() {
+        assert_eq!(closest_match("Publish", COMMANDS.iter().copied()), Some("publish"));
+    }
+
+    #[test]
+    fn prefers_the_earliest_of_equally_close_candidates() {
+        assert_eq!(closest_match("rest", ["test", "best"]), Some("test"));
+    }
+
+    #[test]
+    fn rejects_distant_candidates() {
+        // Short inputs allow a single edit, longer ones one per three characters
+        assert_eq!(closest_match("xyz", COMMANDS.iter().copied()), None);
+        // A swap of two letters counts as two edits
+        assert_eq!(closest_match("tset", COMMANDS.iter().copied()), None);
+        assert_eq!(closest_match("compile", COMMANDS.iter().copied()), None);
+        assert_eq!(closest_match("build", []), None);
+    }
+
+    #[test]
+    fn measures_edit_distance() {
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("same", "same"), 0);
+    }
+}
@@ -0,0 +1,190 @@
+use std::path::{Path, PathBuf};
+
+use toml_edit::{DocumentMut, InlineTable, Value};
+
+use crate::config::Config;
+use crate::diagnostic::closest_match;
+use crate::error::{Error, Result};
+use crate::registry::Registry;
+use crate::workspace::{self, Update, Workspace};
+
+/// Dependency as given to `cbuild add`
+#[derive(Clone, Debug, Default)]
+pub struct AddRequest {
+    pub name: String,
+
+    /// Version requirement after `@`, the latest version when unset
+    pub version: Option<String>,
+
+    /// Relative to the working directory
+    pub path: Option<String>,
+
+    pub git: Option<String>,
+    pub branch: Option<String>,
+    pub tag: Option<String>,
+    pub rev: Option<String>,
+}
+
+impl AddRequest {
+    /// Parses `NAME[@VERSION]`
+    pub fn parse(spec: &str) -> Result<Self> {
+        let (name, version) = match spec.split_once('@') {
+            Some((name, version)) => (name, Some(version.to_string())),
+            None => (spec, None),
+        };
+        if name.is_empty() || version.as_deref() == Some("") {
+            return Err(Error::Arguments(format!("Invalid dependency `{}`, expected NAME or NAME@VERSION", spec)));
+        }
+
+        Ok(AddRequest {
+            name: name.to_string(),
+            version,
+            ..AddRequest::default()
+        })
+    }
+}
+
+/// Adds a dependency to the manifest of the package named `package`, or
+/// of the root project, and resolves it into the lockfile
+pub fn add(root: &Config, package: Option<&str>, request: &AddRequest) -> Result<()> {
+    let (manifest, package_name) = manifest_of(root, package)?;
+    let original = std::fs::read_to_string(&manifest)?;
+    let mut document = parse(&manifest, &original)?;
+
+    let value = dependency_value(root, &manifest, request)?;
+    let description = value.to_string();
+    let dependencies = document.entry("dependencies")
+        .or_insert(toml_edit::table())
+        .as_table_like_mut()
+        .ok_or_else(|| Error::Config(format!("`dependencies` in {} must be a table", manifest.display())))?;
+
+    let replaced = dependencies.insert(&request.name, toml_edit::Item::Value(value)).is_some();
+
+    save_and_resolve(root, &manifest, &original, &document, Update::Packages(vec![request.name.clone()]))?;
+    if replaced {
+        println!("Changed {} to {} in dependencies of `{}`", request.name, description.trim(), package_name);
+    }
+    else {
+        println!("Added {} {} to dependencies of `{}`", request.name, description.trim(), package_name);
+    }
+    Ok(())
+}
+
+/// Removes the dependency `name` from the manifest of the package named
+/// `package`, or of the root project, and from the lockfile
+pub fn remove(root: &Config, package: Option<&str>, name: &str) -> Result<()> {
+    let (manifest, package_name) = manifest_of(root, package)?;
+    let original = std::fs::read_to_string(&manifest)?;
+    let mut document = parse(&manifest, &original)?;
+
+    let not_found = |hint: Option<&str>| {
+        let hint = hint.map(|candidate| format!(", did you mean `{}`?", candidate)).unwrap_or_default();
+        Error::Arguments(format!("`{}` is not a dependency of `{}`{}", name, package_name, hint))
+    };
+    let Some(dependencies) = document.get_mut("dependencies").and_then(|item| item.as_table_like_mut()) else {
+        return Err(not_found(None));
+    };
+    if dependencies.remove(name).is_none() {
+        return Err(not_found(closest_match(name, dependencies.iter().map(|(key, _)| key))));
+    }
+
+    save_and_resolve(root, &manifest, &original, &document, Update::Locked)?;
+    println!("Removed {} from dependencies of `{}`", name, package_name);
+    Ok(())
+}
+
+/// Manifest and name of the package named `package`, or of the root
+/// project
+fn manifest_of(root: &Config, package: Option<&str>) -> Result<(PathBuf, String)> {
+    let config = match package {
+        Some(name) => Workspace::load(root, &Update::Locked)?.package(name)?.config.clone(),
+        None if root.project_name.is_some() => root.clone(),
+        None => {
+            return Err(Error::Arguments(
+                "The workspace root has no project, choose a member with `-p NAME`".to_string()));
+        },
+    };
+
+    let manifest = config.manifest.ok_or(Error::NoConfig())?;
+    Ok((manifest, config.project_name.unwrap_or_default()))
+}
+
+fn parse(manifest: &Path, contents: &str) -> Result<DocumentMut> {
+    contents.parse().map_err(|e: toml_edit::TomlError| {
+        Error::Config(format!("{}: {}", manifest.display(), e.message().trim()))
+    })
+}
+
+/// Manifest value for `request`: a version requirement, or an inline table
+/// for path and git dependencies
+fn dependency_value(root: &Config, manifest: &Path, request: &AddRequest) -> Result<Value> {
+    if request.path.is_none() && request.git.is_none() {
+        if request.branch.is_some() || request.tag.is_some() || request.rev.is_some() {
+            return Err(Error::Arguments("`--branch`, `--tag` and `--rev` need `--git`".to_string()));
+        }
+        let version = match &request.version {
+            Some(version) => version.clone(),
+            None => format!("^{}", latest_version(root, &request.name)?),
+        };
+        return Ok(Value::from(version));
+    }
+
+    let mut table = InlineTable::new();
+    if let Some(version) = &request.version {
+        table.insert("version", version.as_str().into());
+    }
+    if let Some(path) = &request.path {
+        table.insert("path", relative_to_manifest(manifest, path).into());
+    }
+    let keys = [("git", &request.git), ("branch", &request.branch), ("tag", &request.tag), ("rev", &request.rev)];
+    for (key, value) in keys {
+        if let Some(value) = value {
+            table.insert(key, value.as_str().into());
+        }
+    }
+    table.fmt();
+
+    Ok(Value::InlineTable(table))
+}
+
+/// Highest published version of `name`, ignoring pre-releases unless there
+/// is nothing else
+fn latest_version(root: &Config, name: &str) -> Result<semver::Version> {
+    let mut registry = Registry::new(&root.registry);
+    let location = registry.location().to_string();
+    let versions = registry.versions(root, name)?.unwrap_or_default();
+
+    versions.iter().rev().find(|(version, _)| version.pre.is_empty())
+        .or_else(|| versions.last())
+        .map(|(version, _)| version.clone())
+        .ok_or_else(|| Error::Library(format!("Package `{}` not found in registry {}", name, location)))
+}
+
+/// `path`, given relative to the working directory, as the manifest
+/// refers to it
+fn relative_to_manifest(manifest: &Path, path: &str) -> String {
+    let absolute = std::env::current_dir().map(|dir| dir.join(path)).ok().and_then(|path| path.canonicalize().ok());
+    match (absolute, manifest.parent()) {
+        (Some(absolute), Some(dir)) => workspace::relative_path(dir, &absolute).to_string_lossy().replace('\\', "/"),
+        _ => path.to_string(),
+    }
+}
+
+/// Writes the edited manifest and resolves the workspace again, which
+/// updates the lockfile. The manifest is restored when that fails.
+fn save_and_resolve(root: &Config, manifest: &Path, original: &str, document: &DocumentMut, update: Update) -> Result<()> {
+    std::fs::write(manifest, document.to_string())?;
+
+    let root_manifest = root.manifest.as_ref().ok_or(Error::NoConfig())?;
+    let result = Config::load_from(root_manifest).and_then(|mut config| {
+        config.verbose = root.verbose;
+        config.offline = root.offline;
+        Workspace::load(&config, &update)
+    });
+    if let Err(e) = result {
+        std::fs::write(manifest, original)?;
+        return Err(e);
+    }
+
+    Ok(())
+}
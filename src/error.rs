@@ -1,18 +1,22 @@
-
 use thiserror::Error;
 
+use crate::diagnostic::Diagnostic;
+
 /// Custom error type
 #[derive(Error, Debug)]
 pub enum Error {
     #[error("IO Error: {0}")]
     IO(#[from] std::io::Error),
 
-    #[error("No configuration file found")]
+    #[error("No cbuild.toml or config.toml found in the current directory or any parent directory")]
     NoConfig(),
 
     #[error("Configuration error: {0}")]
     Config(String),
 
+    #[error("{}", .0.iter().map(|d| d.to_string()).collect::<Vec<_>>().join("\n\n"))]
+    Diagnostics(Vec<Diagnostic>),
+
     #[error("Argument error: {0}")]
     Arguments(String),
 
@@ -25,8 +29,13 @@ pub enum Error {
     #[error("Build failed")]
     BuildFailed(),
 
-    #[error("Run failed with exit code: {0:?}")]
-    RunFailed(Option<i32>),
+    #[error("{0} test(s) failed")]
+    TestsFailed(usize),
+
+    /// The program started by `cbuild run` failed, cbuild exits with the
+    /// same code
+    #[error("Run failed with exit code: {0}")]
+    RunFailed(i32),
 }
 
 /// Custom Result type alias
@@ -0,0 +1,308 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use sha2::{Digest, Sha256};
+
+use crate::build::{self, Exports};
+use crate::checksum;
+use crate::config::{Compiler, Config, ConfigValue, Language};
+use crate::error::{Error, Result};
+use crate::log;
+use crate::pkgconfig;
+use crate::profile::{DebugInfo, OptLevel};
+use crate::vendor;
+
+/// Directory under the profile directory holding foreign builds
+const FOREIGN_DIR: &str = "foreign";
+
+/// Records what an installed prefix was built from
+const FINGERPRINT_FILE: &str = "fingerprint";
+
+/// Build system of a dependency that is not a cbuild project
+#[derive(Clone, Debug, PartialEq)]
+pub enum BuildSystem {
+    /// `cmake` configure, build and install
+    CMake,
+
+    /// `make` and `make install` with `PREFIX` and `prefix` set
+    Make,
+
+    /// `./configure --prefix`, `make` and `make install`
+    Configure,
+}
+
+impl ConfigValue for BuildSystem {
+    const VALUES: &'static [(&'static str, Self)] = &[
+        ("cmake", BuildSystem::CMake),
+        ("make", BuildSystem::Make),
+        ("configure", BuildSystem::Configure),
+    ];
+}
+
+impl std::fmt::Display for BuildSystem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BuildSystem::CMake => write!(f, "cmake"),
+            BuildSystem::Make => write!(f, "make"),
+            BuildSystem::Configure => write!(f, "configure"),
+        }
+    }
+}
+
+/// How a foreign dependency is built, from its `build` and `options`
+#[derive(Clone, Debug)]
+pub struct ForeignBuild {
+    pub system: BuildSystem,
+
+    /// `-DKEY=VALUE` for cmake, `KEY=VALUE` for make and `--KEY=VALUE`, or
+    /// just `--KEY` when the value is empty, for configure
+    pub options: BTreeMap<String, String>,
+}
+
+/// Builds the foreign project of `config` and installs it into its prefix,
+/// unless the prefix was installed from the same sources and options
+pub fn build(config: &Config, foreign: &ForeignBuild) -> Result<()> {
+    let name = config.project_name.as_deref().unwrap_or_default();
+    let dir = foreign_dir(config);
+    let prefix = prefix_dir(config);
+    let fingerprint = fingerprint(config, foreign)?;
+
+    let stored = std::fs::read_to_string(dir.join(FINGERPRINT_FILE)).ok();
+    if prefix.is_dir() && stored.as_deref() == Some(fingerprint.as_str()) {
+        log(config, &format!("Up to date: {}", prefix.display()));
+        return Ok(());
+    }
+
+    println!("Building `{}` with {}", name, foreign.system);
+    if dir.exists() {
+        std::fs::remove_dir_all(&dir)?;
+    }
+    std::fs::create_dir_all(&dir)?;
+
+    let build_dir = dir.join("build");
+    let log_file = dir.join("build.log");
+    let jobs = build::job_count(config).to_string();
+    let prefix_arg = prefix.to_str().unwrap().to_string();
+
+    match foreign.system {
+        BuildSystem::CMake => {
+            let mut configure = vec![
+                "-S".to_string(), config.root.to_str().unwrap().to_string(),
+                "-B".to_string(), build_dir.to_str().unwrap().to_string(),
+                format!("-DCMAKE_INSTALL_PREFIX={}", prefix_arg),
+                "-DCMAKE_INSTALL_LIBDIR=lib".to_string(),
+                format!("-DCMAKE_BUILD_TYPE={}", cmake_build_type(config)),
+            ];
+            configure.extend(foreign.options.iter().map(|(key, value)| format!("-D{}={}", key, value)));
+            run(config, name, "cmake", &configure, None, &log_file)?;
+
+            let build_dir = build_dir.to_str().unwrap().to_string();
+            run(config, name, "cmake", &["--build".to_string(), build_dir.clone(), "--parallel".to_string(), jobs], None, &log_file)?;
+            run(config, name, "cmake", &["--install".to_string(), build_dir], None, &log_file)?;
+        },
+        BuildSystem::Make => {
+            // Builds in a copy, so that the sources stay as they were fetched
+            vendor::copy_dir(&config.root, &build_dir)?;
+            let mut variables = vec![format!("PREFIX={}", prefix_arg), format!("prefix={}", prefix_arg)];
+            variables.extend(foreign.options.iter().map(|(key, value)| format!("{}={}", key, value)));
+
+            let mut make = vec![format!("-j{}", jobs)];
+            make.extend(variables.iter().cloned());
+            run(config, name, "make", &make, Some(&build_dir), &log_file)?;
+
+            let mut install = vec!["install".to_string()];
+            install.extend(variables);
+            run(config, name, "make", &install, Some(&build_dir), &log_file)?;
+        },
+        BuildSystem::Configure => {
+            vendor::copy_dir(&config.root, &build_dir)?;
+            let mut configure = vec![format!("--prefix={}", prefix_arg)];
+            configure.extend(foreign.options.iter().map(|(key, value)| match value.as_str() {
+                "" => format!("--{}", key),
+                _ => format!("--{}={}", key, value),
+            }));
+            run(config, name, "./configure", &configure, Some(&build_dir), &log_file)?;
+            run(config, name, "make", &[format!("-j{}", jobs)], Some(&build_dir), &log_file)?;
+            run(config, name, "make", &["install".to_string()], Some(&build_dir), &log_file)?;
+        },
+    }
+
+    std::fs::write(dir.join(FINGERPRINT_FILE), fingerprint)?;
+    println!("Built `{}` into {}", name, prefix.display());
+    Ok(())
+}
+
+/// Headers and libraries installed into the prefix of the foreign project
+/// of `config`. A library installed both static and shared is linked
+/// statically. The libraries are linked in the order the `.pc` files
+/// installed with them give, followed by any others in alphabetical order.
+pub fn exports(config: &Config) -> Exports {
+    let prefix = prefix_dir(config);
+
+    let mut libraries: BTreeMap<String, PathBuf> = BTreeMap::new();
+    for lib_dir in [prefix.join("lib"), prefix.join("lib64")] {
+        let Ok(entries) = std::fs::read_dir(&lib_dir) else {
+            continue;
+        };
+        for path in entries.flatten().map(|entry| entry.path()) {
+            let file_name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+            let Some((stem, extension)) = file_name.rsplit_once('.') else {
+                continue;
+            };
+            let is_static = matches!(extension, "a" | "lib");
+            if !is_static && !matches!(extension, "so" | "dylib") {
+                continue;
+            }
+            if is_static || !libraries.contains_key(stem) {
+                libraries.insert(stem.to_string(), path);
+            }
+        }
+    }
+
+    let pc_dirs: Vec<PathBuf> = ["lib/pkgconfig", "lib64/pkgconfig", "share/pkgconfig"].iter()
+        .map(|dir| prefix.join(dir))
+        .filter(|dir| dir.is_dir())
+        .collect();
+    let order = pkgconfig::installed_libraries(config, &pc_dirs).unwrap_or_else(|e| {
+        log(config, &format!("Linking the libraries of `{}` in alphabetical order: {}",
+            config.project_name.as_deref().unwrap_or_default(), e));
+        Vec::new()
+    });
+
+    let mut exports = Exports {
+        include_dirs: vec![prefix.join("include")],
+        needs_cpp: has_cpp_sources(&config.root),
+        ..Exports::default()
+    };
+    for library in order {
+        let installed = libraries.remove(&format!("lib{}", library)).or_else(|| libraries.remove(&library));
+        match installed {
+            Some(path) => exports.link_inputs.push(path),
+            // Libraries the installed ones need in turn, such as `m`
+            None => exports.link_libraries.push(library),
+        }
+    }
+    exports.link_inputs.extend(libraries.into_values());
+    exports
+}
+
+/// Whether the sources of the foreign project in `dir` include C++ files,
+/// so that the C++ runtime must be linked
+fn has_cpp_sources(dir: &Path) -> bool {
+    let mut directories = vec![dir.to_path_buf()];
+    while let Some(current) = directories.pop() {
+        let Ok(entries) = std::fs::read_dir(&current) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            if file_type.is_dir() {
+                if !checksum::is_generated(&entry.file_name()) {
+                    directories.push(entry.path());
+                }
+            }
+            else if matches!(build::source_language(&entry.path()), Some(Language::CPP)) {
+                return true;
+            }
+        }
+    }
+    false
+}
+
+/// `bin/{profile}/foreign/{name}/`, holding the build and its prefix
+fn foreign_dir(config: &Config) -> PathBuf {
+    build::profile_dir(config)
+        .join(FOREIGN_DIR)
+        .join(config.project_name.as_deref().unwrap_or_default())
+}
+
+fn prefix_dir(config: &Config) -> PathBuf {
+    foreign_dir(config).join("prefix")
+}
+
+/// Changes whenever the sources, the options or the toolchain and profile
+/// they are built with change
+fn fingerprint(config: &Config, foreign: &ForeignBuild) -> Result<String> {
+    let mut hasher = Sha256::new();
+    hasher.update(foreign.system.to_string());
+    for (key, value) in &foreign.options {
+        hasher.update(format!("\0{}={}", key, value));
+    }
+    for (key, value) in environment(config) {
+        hasher.update(format!("\0{}={}", key, value));
+    }
+    hasher.update(format!("\0{}", cmake_build_type(config)));
+    hasher.update(checksum::sha256_dir(&config.root)?);
+    Ok(checksum::hex(&hasher.finalize()))
+}
+
+/// Compilers and flags passed to the foreign build, matching the
+/// toolchain and profile of the workspace
+fn environment(config: &Config) -> Vec<(&'static str, String)> {
+    let compiler = &config.settings.compiler;
+    if matches!(compiler, Compiler::MSVC) {
+        return Vec::new();
+    }
+
+    let mut flags = vec![match config.profile.opt_level {
+        OptLevel::O0 => "-O0",
+        OptLevel::O1 => "-O1",
+        OptLevel::O2 => "-O2",
+        OptLevel::O3 => "-O3",
+        OptLevel::Os | OptLevel::Oz => "-Os",
+    }];
+    match config.profile.debug {
+        DebugInfo::None => (),
+        DebugInfo::Limited => flags.push("-g1"),
+        DebugInfo::Full => flags.push("-g"),
+    }
+    let flags = flags.join(" ");
+
+    vec![
+        ("CC", build::compiler_driver(compiler, &Language::C).to_string()),
+        ("CXX", build::compiler_driver(compiler, &Language::CPP).to_string()),
+        ("CFLAGS", flags.clone()),
+        ("CXXFLAGS", flags),
+    ]
+}
+
+fn cmake_build_type(config: &Config) -> &'static str {
+    match (&config.profile.opt_level, &config.profile.debug) {
+        (OptLevel::O0, _) => "Debug",
+        (OptLevel::Os | OptLevel::Oz, _) => "MinSizeRel",
+        (_, DebugInfo::None) => "Release",
+        _ => "RelWithDebInfo",
+    }
+}
+
+/// Runs one step of a foreign build, appending its output to `log_file`
+fn run(config: &Config, name: &str, program: &str, args: &[String], dir: Option<&Path>, log_file: &Path) -> Result<()> {
+    let command_line = format!("{} {}", program, args.join(" "));
+    log(config, &format!("Running command: {}", command_line));
+
+    let mut command = std::process::Command::new(program);
+    if let Some(dir) = dir {
+        command.current_dir(dir);
+    }
+    command.envs(environment(config));
+    let output = command.args(args).output().map_err(|e| {
+        Error::Library(format!("Failed to run `{}` for `{}`: {}", program, name, e))
+    })?;
+
+    let mut file = std::fs::OpenOptions::new().create(true).append(true).open(log_file)?;
+    writeln!(file, "$ {}", command_line)?;
+    file.write_all(&output.stdout)?;
+    file.write_all(&output.stderr)?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let lines: Vec<&str> = stderr.lines().collect();
+        let tail = lines[lines.len().saturating_sub(20)..].join("\n");
+        return Err(Error::Library(format!("`{}` failed for `{}`, the full output is in {}\n{}",
+            command_line, name, log_file.display(), tail)));
+    }
+    Ok(())
+}
@@ -0,0 +1,126 @@
+use std::path::{Path, PathBuf};
+
+use sha2::{Digest, Sha256};
+
+use crate::checksum;
+use crate::config::{self, Config};
+use crate::dependency::GitReference;
+use crate::error::{Error, Result};
+use crate::log;
+
+/// Bare clones of git dependencies and checkouts of the commits in use
+const GIT_CACHE_PATH: &str = "~/.cbuild/git/";
+
+/// Marks a checkout as complete, so that interrupted ones are redone
+const CHECKOUT_READY: &str = ".cbuild-ready";
+
+/// Fetches `url` and returns the commit `reference` currently points to
+pub fn resolve(config: &Config, url: &str, reference: &GitReference) -> Result<String> {
+    let database = fetch(config, url)?;
+    rev_parse(config, &database, &reference.rev_spec()).ok_or_else(|| {
+        Error::Library(format!("Could not find {} in git repository {}", reference, url))
+    })
+}
+
+/// Directory with `commit` of `url` checked out, fetching the repository
+/// when the commit isn't in the cache yet
+pub fn checkout(config: &Config, url: &str, commit: &str) -> Result<PathBuf> {
+    let dir = cache_dir().join("checkouts").join(repository_name(url)).join(commit);
+    if dir.join(CHECKOUT_READY).exists() {
+        return Ok(dir);
+    }
+
+    let database = database_dir(url);
+    if rev_parse(config, &database, commit).is_none() {
+        fetch(config, url)?;
+        if rev_parse(config, &database, commit).is_none() {
+            return Err(Error::Library(format!("Commit {} not found in git repository {}", commit, url)));
+        }
+    }
+
+    println!("Checking out {} at {}", url, short_commit(commit));
+    if dir.exists() {
+        std::fs::remove_dir_all(&dir)?;
+    }
+    git(config, None, &["clone", "--quiet", "--no-checkout", path_arg(&database), path_arg(&dir)])?;
+    git(config, Some(&dir), &["checkout", "--quiet", "--detach", commit])?;
+    std::fs::write(dir.join(CHECKOUT_READY), "")?;
+
+    Ok(dir)
+}
+
+/// Clones or updates the bare repository of `url` in the cache
+fn fetch(config: &Config, url: &str) -> Result<PathBuf> {
+    if !url.starts_with("file://") && !Path::new(url).exists() {
+        config::require_network(config, url)?;
+    }
+    let database = database_dir(url);
+    println!("Fetching {}", url);
+
+    if database.exists() {
+        git(config, Some(&database), &["fetch", "--quiet", "--force", "--tags", url, "+refs/heads/*:refs/heads/*"])?;
+    }
+    else {
+        std::fs::create_dir_all(database.parent().unwrap())?;
+        git(config, None, &["clone", "--quiet", "--bare", url, path_arg(&database)])?;
+    }
+
+    Ok(database)
+}
+
+/// Full commit hash `rev` names in `repository`
+fn rev_parse(config: &Config, repository: &Path, rev: &str) -> Option<String> {
+    if !repository.exists() {
+        return None;
+    }
+
+    git(config, Some(repository), &["rev-parse", "--verify", "--quiet", &format!("{}^{{commit}}", rev)])
+        .ok()
+        .map(|commit| commit.trim().to_string())
+}
+
+/// Runs git, returning its standard output
+fn git(config: &Config, dir: Option<&Path>, args: &[&str]) -> Result<String> {
+    log(config, &format!("Running command: git {}", args.join(" ")));
+
+    let mut command = std::process::Command::new("git");
+    if let Some(dir) = dir {
+        command.current_dir(dir);
+    }
+    let output = command.args(args).output().map_err(|e| {
+        Error::Library(format!("Failed to run git: {}", e))
+    })?;
+
+    if !output.status.success() {
+        return Err(Error::Library(format!("git {} failed: {}",
+            args[0], String::from_utf8_lossy(&output.stderr).trim())));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// Abbreviated commit hash for messages
+pub fn short_commit(commit: &str) -> &str {
+    &commit[..commit.len().min(12)]
+}
+
+fn path_arg(path: &Path) -> &str {
+    path.to_str().unwrap()
+}
+
+fn cache_dir() -> PathBuf {
+    PathBuf::from(shellexpand::tilde(GIT_CACHE_PATH).to_string())
+}
+
+fn database_dir(url: &str) -> PathBuf {
+    cache_dir().join("db").join(repository_name(url))
+}
+
+/// Readable and unique cache directory name for `url`, such as
+/// `foo-1a2b3c4d5e6f7a8b`
+fn repository_name(url: &str) -> String {
+    let last = url.trim_end_matches('/').rsplit(['/', ':']).next().unwrap_or_default();
+    let stem = last.strip_suffix(".git").unwrap_or(last);
+    let hash = Sha256::digest(url.as_bytes());
+    format!("{}-{}", stem, checksum::hex(&hash[..8]))
+}
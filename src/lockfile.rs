@@ -0,0 +1,88 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, Result};
+
+/// Written next to the root manifest
+pub const LOCKFILE: &str = "cbuild.lock";
+
+const HEADER: &str = "# This file is generated by cbuild, do not edit it by hand.\n\n";
+
+/// Exact set of dependencies a build resolved to, so that later builds use
+/// and verify the same code until `cbuild update` resolves them again
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Lockfile {
+    #[serde(default, rename = "package")]
+    pub packages: Vec<LockedPackage>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct LockedPackage {
+    pub name: String,
+
+    /// Where the dependency comes from, see `dependency::Source`
+    pub source: String,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+
+    /// Git commit
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commit: Option<String>,
+
+    /// SHA-256 of the contents, checked on every build
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checksum: Option<String>,
+}
+
+impl LockedPackage {
+    /// Short description of the locked revision for messages
+    pub fn revision(&self) -> String {
+        match (&self.commit, &self.version, &self.checksum) {
+            (Some(commit), _, _) => commit[..commit.len().min(12)].to_string(),
+            (None, Some(version), _) => format!("v{}", version),
+            (None, None, Some(checksum)) => format!("sha256:{}", &checksum[..checksum.len().min(12)]),
+            (None, None, None) => "(unversioned)".to_string(),
+        }
+    }
+}
+
+impl Lockfile {
+    /// Reads the lockfile in `root`, which is empty when there is none
+    pub fn load(root: &Path) -> Result<Self> {
+        let path = root.join(LOCKFILE);
+        if !path.exists() {
+            return Ok(Lockfile::default());
+        }
+
+        let contents = std::fs::read_to_string(&path)?;
+        toml::from_str(&contents).map_err(|e| {
+            Error::Library(format!("Invalid {}: {}", path.display(), e.message()))
+        })
+    }
+
+    /// Writes the lockfile to `root` unless it is unchanged
+    pub fn save(&self, root: &Path) -> Result<()> {
+        let path = root.join(LOCKFILE);
+        let mut contents = HEADER.to_string();
+        contents.push_str(&toml::to_string(self).map_err(|e| Error::Library(e.to_string()))?);
+
+        if std::fs::read_to_string(&path).is_ok_and(|existing| existing == contents) {
+            return Ok(());
+        }
+        std::fs::write(path, contents)?;
+        Ok(())
+    }
+
+    pub fn find(&self, name: &str, source: &str) -> Option<&LockedPackage> {
+        self.packages.iter().find(|package| package.name == name && package.source == source)
+    }
+
+    pub fn insert(&mut self, package: LockedPackage) {
+        if self.find(&package.name, &package.source).is_none() {
+            self.packages.push(package);
+            self.packages.sort_by(|a, b| a.name.cmp(&b.name).then_with(|| a.source.cmp(&b.source)));
+        }
+    }
+}
@@ -1,279 +1,188 @@
-
 use std::io::Write;
-use std::path::PathBuf;
-
+use std::path::{Path, PathBuf};
+use std::process::ExitStatus;
+
+mod archive;
+mod build;
+mod checksum;
+mod cli;
+mod config;
+mod dependency;
+mod edit;
+mod diagnostic;
 mod error;
+mod foreign;
+mod git;
+mod lockfile;
+mod packaging;
+mod pkgconfig;
+mod profile;
+mod registry;
+mod test_runner;
+mod tree;
+mod vendor;
+mod workspace;
+use config::{Compiler, Config, ConfigValue, Language, Mode, Type};
 use error::{Error, Result};
+use workspace::{Update, Workspace};
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
-const GLOBAL_LIB_PATH: &str = "~/.cbuild/libs/";
+pub const GLOBAL_LIB_PATH: &str = "~/.cbuild/libs/";
 const TEMP_BUILD_DIR: &str = "./.cbuild";
 
-/// Programming languages
 #[derive(Clone, Debug)]
-enum Language {
-    C,
-    CPP,
-}
+struct Arguments {
+    command: String,
+    config: Config,
+    file: Option<String>,
 
-/// Programming language standards
-#[derive(Clone, Debug)]
-enum Standard {
-    C89,
-    C99,
-    C11,
-    C17,
-    CPP98,
-    CPP11,
-    CPP14,
-    CPP17,
-    CPP20,
-}
+    /// Only build the tests of `cbuild test` without running them
+    no_run: bool,
 
-/// Compilers
-#[derive(Clone, Debug)]
-enum Compiler {
-    /// GNU Compiler Collection (linux default)
-    GCC,
+    /// Arguments after `--`, passed on to the program by `cbuild run`
+    program_args: Vec<String>,
+
+    /// Workspace package the command applies to, all of them if unset
+    package: Option<String>,
 
-    /// C/C++ LLVM Compiler
-    CLANG,
+    /// Dependency `cbuild add` adds
+    add: Option<edit::AddRequest>,
 
-    /// Microsoft Visual C++ (windows default)
-    MSVC,
+    tree: tree::TreeOptions,
 }
 
-/// Build type
-#[derive(Clone, Debug)]
-enum Type {
-    /// Standard binary executable (default)
-    Binary,
+fn parse_arguments() -> Result<Arguments> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let matches = cli::parse(&args)?;
+
+    // `cbuild <COMMAND> --help` works without a project
+    if matches.flag("help") {
+        return Ok(Arguments {
+            command: "help".to_string(),
+            config: Config::default(),
+            file: Some(matches.command),
+            no_run: false,
+            program_args: Vec::new(),
+            package: None,
+            add: None,
+            tree: tree::TreeOptions::default(),
+        });
+    }
 
-    /// `.lib` library file
-    Library,
+    let mut default_package = None;
+    let mut config = match matches.command.as_str() {
+        // A single source file can be run outside of any project
+        "run" if matches.positional(0).is_some() => match load_config(&matches) {
+            Err(Error::NoConfig()) => Config::default(),
+            result => result?,
+        },
+        "build" | "run" | "test" | "clean" | "compdb" | "update" | "tree" | "add" | "remove" | "vendor" | "package" | "publish" => {
+            let (config, member) = workspace::find_root(load_config(&matches)?)?;
+            default_package = member;
+            config
+        },
+        "new" => {
+            let project_name = matches.positional(0).ok_or_else(|| {
+                Error::Arguments("Project name is required for `new` command".to_string())
+            })?;
+            Config::new(project_name)
+        },
+        _ => Config::default(),
+    };
 
-    /// `.dll` dynamic library file
-    DynLibrary,
-}
+    config.verbose = matches.flag("verbose");
+    config.offline = matches.flag("offline");
+    config.keep_going = matches.flag("keep-going");
+    apply_overrides(&mut config, &matches)?;
 
-/// Build target
-#[derive(Clone, Debug, PartialEq)]
-enum Target {
-    X86_64,
-}
+    let file = match matches.command.as_str() {
+        "new" => None,
+        _ => matches.positional(0).map(str::to_string),
+    };
 
-/// Build mode
-#[derive(Clone, Debug)]
-enum Mode {
-    /// (default)
-    Debug,
+    let add = match matches.command.as_str() {
+        "add" => {
+            let spec = file.as_deref().ok_or_else(|| {
+                Error::Arguments("Dependency name is required for `add` command".to_string())
+            })?;
+            Some(edit::AddRequest {
+                path: matches.value("path").map(str::to_string),
+                git: matches.value("git").map(str::to_string),
+                branch: matches.value("branch").map(str::to_string),
+                tag: matches.value("tag").map(str::to_string),
+                rev: matches.value("rev").map(str::to_string),
+                ..edit::AddRequest::parse(spec)?
+            })
+        },
+        "remove" if file.is_none() => {
+            return Err(Error::Arguments("Dependency name is required for `remove` command".to_string()));
+        },
+        _ => None,
+    };
 
-    /// Debug symbols removed and optimization enabled
-    Release,
-}
+    let tree = tree::TreeOptions {
+        invert: matches.value("invert").map(str::to_string),
+        format: matches.value("format").map(tree::Format::parse).transpose()?.unwrap_or_default(),
+    };
 
-#[derive(Clone, Debug)]
-struct Settings {
-    language: Language,
-    standard: Standard,
-    compiler: Compiler,
-    build_type: Type,
-    target: Target,
-    mode: Mode,
+    Ok(Arguments {
+        command: matches.command.clone(),
+        config,
+        file,
+        no_run: matches.flag("no-run"),
+        package: matches.value("package").map(str::to_string).or(default_package),
+        program_args: matches.passthrough,
+        add,
+        tree,
+    })
 }
 
-impl Default for Settings {
-    fn default() -> Self {
-        Settings {
-            language: Language::C,
-            standard: Standard::C89,
-            compiler: Compiler::GCC,
-            build_type: Type::Binary,
-            target: Target::X86_64,
-            mode: Mode::Debug,
-        }
-    }
-}
+/// Loads the manifest given with `--manifest-path`, or searches for one
+fn load_config(matches: &cli::Matches) -> Result<Config> {
+    let Some(manifest_path) = matches.value("manifest-path") else {
+        return Config::load();
+    };
 
-#[derive(Clone, Debug)]
-struct Config {
-    project_name: Option<String>,
-    settings: Settings,
-    libraries: Vec<String>,
-    verbose: bool,
+    Config::load_from(Path::new(manifest_path))
 }
 
-impl Default for Config {
-    fn default() -> Self {
-        Config {
-            project_name: None,
-            settings: Settings::default(),
-            libraries: Vec::new(),
-            verbose: false,
-        }
+/// Applies the command-line flags overriding `[settings]` and the profile
+fn apply_overrides(config: &mut Config, matches: &cli::Matches) -> Result<()> {
+    if let Some(value) = matches.value("compiler") {
+        config.settings.compiler = parse_flag("compiler", value)?;
     }
-}
-
-impl Config {
-    pub fn new(project_name: &str) -> Self {
-        Config {
-            project_name: Some(project_name.to_string()),
-            settings: Settings::default(),
-            libraries: Vec::new(),
-            verbose: false,
-        }
+    if let Some(value) = matches.value("standard") {
+        config.override_standard(parse_flag("standard", value)?);
     }
-
-    pub fn load() -> Result<Self> {
-        let working_directory = std::env::current_dir()?;
-        let config_file = Self::find_config_file(&working_directory)?;
-
-        let contents = std::fs::read_to_string(config_file)?;
-        Ok(parse_config_toml(&contents)?)
+    if let Some(value) = matches.value("target") {
+        config.settings.target = parse_flag("target", value)?;
     }
 
-    fn find_config_file(path: &std::path::Path) -> Result<std::path::PathBuf> {
-        let mut directories = vec![path.to_path_buf()];
-        while let Some(dir) = directories.pop() {
-            if let Ok(entries) = std::fs::read_dir(dir) {
-                for entry in entries.flatten() {
-                    let path = entry.path();
-                    if path.is_dir() {
-                        directories.push(path);
-                    }
-                    else if path.file_name().map_or(
-                            false, |name| name == "config.toml") {
-                        return Ok(path);
-                    }
-                }
-            }
-        }
-
-        Err(Error::NoConfig())
+    if let Some(value) = matches.value("mode") {
+        config.settings.mode = parse_flag("mode", value)?;
+        config.select_profile(config.settings.mode.profile_name())?;
     }
-}
-
-#[derive(Clone, Debug)]
-struct Arguments {
-    command: String,
-    config: Config,
-    file: Option<String>,
-}
-
-fn parse_config_toml(config: &str) -> Result<Config> {
-    let mut project_name = None;
-    let mut settings = Settings::default();
-    let mut libraries = Vec::new();
-
-    for line in config.lines() {
-        let parts: Vec<&str> = line.split('=').map(|part| part.trim()).collect();
-        if parts.len() == 2 {
-            match parts[0] {
-                "name" => {
-                    project_name = Some(parts[1].trim_matches('"').to_string());
-                },
-                "language" => {
-                    settings.language = match parts[1].trim_matches('"') {
-                        "c" => Language::C,
-                        "CPP" => Language::CPP,
-                        _ => return Err(Error::Config("Unsupported language".to_string())),
-                    }
-                },
-                "standard" => {
-                    settings.standard = match parts[1].trim_matches('"') {
-                        "c89" => Standard::C89,
-                        "c99" => Standard::C99,
-                        "c11" => Standard::C11,
-                        "c17" => Standard::C17,
-                        "CPP98" => Standard::CPP98,
-                        "CPP11" => Standard::CPP11,
-                        "CPP14" => Standard::CPP14,
-                        "CPP17" => Standard::CPP17,
-                        "CPP20" => Standard::CPP20,
-                        _ => return Err(Error::Config("Unsupported standard".to_string())),
-                    }
-                },
-                "compiler" => {
-                    settings.compiler = match parts[1].trim_matches('"') {
-                        "gcc"   => Compiler::GCC,
-                        "clang" => Compiler::CLANG,
-                        "msvc"  => Compiler::MSVC,
-                        _ => return Err(Error::Config("Unsupported compiler".to_string())),
-                    }
-                },
-                "type" => {
-                    settings.build_type = match parts[1].trim_matches('"') {
-                        "bin"   => Type::Binary,
-                        "lib"   => Type::Library,
-                        "dylib" => Type::DynLibrary,
-                        _ => return Err(Error::Config("Unsupported type".to_string())),
-                    }
-                },
-                "target" => {
-                    settings.target = match parts[1].trim_matches('"') {
-                        "x86_64" => Target::X86_64,
-                        _ => return Err(Error::Config("Unsupported target".to_string())),
-                    }
-                },
-                "mode" => {
-                    settings.mode = match parts[1].trim_matches('"') {
-                        "debug"   => Mode::Debug,
-                        "release" => Mode::Release,
-                        _ => return Err(Error::Config("Unsupported mode".to_string())),
-                    }
-                },
-                "libraries" => {
-                    libraries = parts[1].trim_matches('"')
-                        .split(',')
-                        .map(|s| s.trim().to_string())
-                        .collect();
-                },
-                _ => (),
-            }
-        }
+    if matches.flag("release") {
+        config.settings.mode = Mode::Release;
+        config.select_profile("release")?;
     }
-
-    Ok(Config {
-        project_name,
-        settings,
-        libraries,
-        verbose: false,
-    })
-}
-
-fn parse_arguments() -> Result<Arguments> {
-    let args: Vec<String> = std::env::args().collect();
-    if args.len() < 2 {
-        return Err(Error::Arguments("Not enough arguments".to_string()));
+    if let Some(profile) = matches.value("profile") {
+        config.select_profile(profile)?;
     }
 
-    let command = &args[1];
-    let mut config = match command.as_str() {
-        "build" | "run" | "clean" => Config::load()?,
-        "new" => {
-            if args.len() < 3 {
-                return Err(Error::Arguments("Project name is required for `new` command".to_string()));
-            }
-            let project_name = args[2].clone();
-            Config::new(&project_name)
-        },
-        "help" | "version" => Config::default(),
-        _ => return Err(Error::Arguments("Unknown command".to_string())),
-    };
-
-    config.verbose = args.contains(&"--verbose".to_string()) || args.contains(&"-v".to_string());
-
-    let file = if command == "run" && args.len() > 2 && !args[2].starts_with('-') {
-      Some(args[2].clone())
+    if let Some(jobs) = matches.value("jobs") {
+        config.jobs = Some(config::parse_jobs(jobs).ok_or_else(|| {
+            Error::Arguments(format!("Invalid job count `{}`", jobs))
+        })?);
     }
-    else {
-      None
-    };
 
-    Ok(Arguments {
-        command: command.clone(),
-        config,
-        file,
+    Ok(())
+}
+
+fn parse_flag<T: ConfigValue>(flag: &str, value: &str) -> Result<T> {
+    T::from_config(value).ok_or_else(|| {
+        Error::Arguments(format!("Unsupported value `{}` for `--{}`, expected one of: {}",
+            value, flag, T::names().join(", ")))
     })
 }
 
@@ -282,8 +191,7 @@ fn create_source_file(file_path: &PathBuf) -> Result<()> {
         return Err(Error::ProjectCreation(format!("File {} already exists", file_path.display())));
     }
 
-    let file_ext = file_path.extension().and_then(|os_str| os_str.to_str()).unwrap_or("c");
-    let is_cpp = file_ext == "cpp";
+    let is_cpp = matches!(build::source_language(file_path), Some(Language::CPP));
 
     let content = if is_cpp {
         r#"#include <iostream>
@@ -311,7 +219,7 @@ int main(int argc, char** argv) {
 
 fn create_new_project(name: &str) -> Result<()> {
     let path = std::path::Path::new(name);
-    if name.ends_with(".c") || name.ends_with(".cpp") {
+    if build::source_language(path).is_some() {
       return create_new_module(name);
     }
     if path.exists() {
@@ -329,11 +237,11 @@ fn create_new_project(name: &str) -> Result<()> {
 
     let config_file_path = path.join("config.toml");
     let mut config_file = std::fs::File::create(config_file_path)?;
-    writeln!(config_file, "[project]\nname = \"{}\"\n\n[settings]\nlanguage = \"c\"\nstandard = \"c99\"\ncompiler = \"gcc\"\ntype = \"bin\"\ntarget = \"x86_64\"\nmode = \"debug\"", name)?;
+    writeln!(config_file, "[project]\nname = \"{}\"\n\n[settings]\nlanguage = \"c\"\nc_standard = \"c99\"\ncpp_standard = \"cpp17\"\ncompiler = \"gcc\"\ntype = \"bin\"\ntarget = \"x86_64\"\nmode = \"debug\"", name)?;
 
     let gitignore_path = path.join(".gitignore");
     let mut gitignore_file = std::fs::File::create(gitignore_path)?;
-    writeln!(gitignore_file, "/bin\n*.o\n*.a\n*.so\n*.dll")?;
+    writeln!(gitignore_file, "/bin\n/compile_commands.json\n*.o\n*.a\n*.so\n*.dll")?;
 
     println!("Created project: {}", name);
 
@@ -348,175 +256,59 @@ fn create_new_module(module_name: &str) -> Result<()> {
     create_source_file(&module_path)
 }
 
-fn manage_dependencies(config: &Config) -> Result<()> {
-    log(config, "Managing dependencies");
-
-    let current_dir = std::env::current_dir()?;
-    let project_lib_path = current_dir.join("lib");
-    std::fs::create_dir_all(&project_lib_path)?;
-
-    let global_lib_path = shellexpand::tilde(GLOBAL_LIB_PATH);
-
-    for lib in &config.libraries {
-        let global_lib_file = std::path::PathBuf::from(global_lib_path.to_string()).join(lib);
-        let project_lib_file = project_lib_path.join(lib);
-
-        if global_lib_file.exists() {
-            if !project_lib_file.exists() {
-                std::fs::copy(&global_lib_file, &project_lib_file)?;
-                println!("Copied dependency: {} to project", lib);
-            } else {
-                println!("Dependency {} already exists in project", lib);
-            }
-        } else {
-            return Err(Error::Library(format!("Library {} not found in global library path", lib)));
-        }
+/// Rejects `run` for projects that don't produce an executable
+fn check_runnable(config: &Config) -> Result<()> {
+    match config.settings.build_type {
+        Type::Binary => Ok(()),
+        Type::Library | Type::DynLibrary => Err(Error::Config(format!(
+            "`{}` is a library project and cannot be run, use `cbuild build` instead",
+            config.project_name.as_deref().unwrap_or_default()))),
     }
-
-    Ok(())
 }
 
-fn build_project(config: Config) -> Result<()> {
-    log(&config, "Starting build process");
-    manage_dependencies(&config)?;
-
-    let current_dir = std::env::current_dir()?;
-    let src_path = current_dir.join("src");
-    let lib_path = current_dir.join("lib");
-    let bin_path = current_dir.join("bin");
-    std::fs::create_dir_all(&bin_path)?;
-
+fn run_project(config: &Config, program_args: &[String]) -> Result<()> {
+    log(config, "Running project");
+    check_runnable(config)?;
     let project_name = config.project_name.as_ref().ok_or_else(|| Error::Config("Project name not found".to_string()))?;
-    let output_file = bin_path.join(project_name);
-
-    let mut source_files = Vec::new();
-    for entry in std::fs::read_dir(&src_path)? {
-        let entry = entry?;
-        let path = entry.path();
-        if path.is_file() && path.extension().map_or(false, |ext| ext == "c" || ext == "cpp") {
-            source_files.push(path);
-        }
-    }
+    let bin_path = build::profile_dir(config).join(project_name);
 
-    if source_files.is_empty() {
-        return Err(Error::Config("No source files found in src directory".to_string()));
+    if !bin_path.exists() {
+        return Err(Error::Config(format!("Binary not found at: {}", bin_path.display())));
     }
 
-    let mut args = Vec::new();
-    let compiler = match config.settings.compiler {
-        Compiler::GCC | Compiler::CLANG => {
-            let compiler = match config.settings.compiler {
-                Compiler::GCC => "gcc",
-                Compiler::CLANG => "clang",
-                _ => unreachable!(),
-            };
-
-            args.push(format!("-o{}", output_file.to_str().unwrap()));
-            args.push(format!("-I{}", lib_path.to_str().unwrap()));
-
-            args.push(match config.settings.standard {
-                Standard::C89 => "-std=c89".to_string(),
-                Standard::C99 => "-std=c99".to_string(),
-                Standard::C11 => "-std=c11".to_string(),
-                Standard::C17 => "-std=c17".to_string(),
-                Standard::CPP98 => "-std=c++98".to_string(),
-                Standard::CPP11 => "-std=c++11".to_string(),
-                Standard::CPP14 => "-std=c++14".to_string(),
-                Standard::CPP17 => "-std=c++17".to_string(),
-                Standard::CPP20 => "-std=c++20".to_string(),
-            });
-
-            match config.settings.mode {
-                Mode::Debug => args.push("-g".to_string()),
-                Mode::Release => {
-                    args.push("-O3".to_string());
-                    args.push("-s".to_string());
-                },
-            }
-
-            if config.settings.target == Target::X86_64 {
-                args.push("-m64".to_string());
-            }
-
-            args.extend(source_files.iter().map(|path| path.to_str().unwrap().to_string()));
-
-            compiler
-        },
-        Compiler::MSVC => {
-            let compiler = "cl.exe";
-            args.push(format!("/Fe:{}", output_file.to_str().unwrap()));
-            args.push(format!("/I{}", lib_path.to_str().unwrap()));
-
-            args.push(match config.settings.standard {
-                Standard::C89 => "/Za".to_string(),
-                Standard::C99 | Standard::C11 | Standard::C17 => "/std:c11".to_string(),
-                Standard::CPP98 | Standard::CPP11 | Standard::CPP14 => "/std:c++14".to_string(),
-                Standard::CPP17 => "/std:c++17".to_string(),
-                Standard::CPP20 => "/std:c++latest".to_string(),
-            });
-
-            match config.settings.mode {
-                Mode::Debug => args.push("/Zi".to_string()),
-                Mode::Release => {
-                    args.push("/O2".to_string());
-                    args.push("/DNDEBUG".to_string());
-                },
-            }
-
-            if config.settings.target == Target::X86_64 {
-                args.push("/MACHINE:X64".to_string());
-            }
-
-            args.extend(source_files.iter().map(|path| path.to_str().unwrap().to_string()));
-
-            compiler
-        },
-    };
+    run_program(config, &bin_path, program_args)
+}
 
-    log(&config, &format!("Running command: {} {}", compiler, args.join(" ")));
+/// Runs `program` attached to cbuild's own stdin, stdout and stderr
+fn run_program(config: &Config, program: &Path, program_args: &[String]) -> Result<()> {
+    log(config, &format!("Running: {} {}", program.display(), program_args.join(" ")));
 
-    let output = std::process::Command::new(compiler)
-        .args(&args)
-        .output()
-        .expect("Failed to execute build command");
+    let status = std::process::Command::new(program)
+        .args(program_args)
+        .status()?;
 
-    if !output.status.success() {
-        std::io::stderr().write_all(&output.stderr)?;
-        return Err(Error::BuildFailed());
+    if !status.success() {
+        return Err(Error::RunFailed(exit_code(status)));
     }
 
-    // @TODO: don't print on `run` mode
-    println!("Built `{}`", project_name);
     Ok(())
 }
 
-fn run_project(config: &Config) -> Result<()> {
-    log(config, "Running project");
-    let project_name = config.project_name.as_ref().ok_or_else(|| Error::Config("Project name not found".to_string()))?;
-    let current_dir = std::env::current_dir()?;
-    let bin_path = current_dir.join("bin").join(project_name);
-
-    if !bin_path.exists() {
-        return Err(Error::Config(format!("Binary not found at: {}", bin_path.display())));
-    }
-
-    log(config, &format!("Attempting to run: {}", bin_path.display()));
-
-    let output = std::process::Command::new(&bin_path)
-        .output()
-        .map_err(|e| Error::IO(e))?;
-
-    std::io::stdout().write_all(&output.stdout)?;
-    std::io::stderr().write_all(&output.stderr)?;
-
-    if !output.status.success() {
-        return Err(Error::RunFailed(output.status.code()));
+/// Exit code mirroring `status`, following the shell convention of 128
+/// plus the signal number for programs killed by a signal
+fn exit_code(status: ExitStatus) -> i32 {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        if let Some(signal) = status.signal() {
+            return 128 + signal;
+        }
     }
 
-    Ok(())
+    status.code().unwrap_or(1)
 }
 
-fn build_and_run_file(config: &Config, file_name: &str) -> Result<()> {
+fn build_and_run_file(config: &Config, file_name: &str, program_args: &[String]) -> Result<()> {
     log(config, &format!("Building and running file: {}", file_name));
 
     let temp_dir = PathBuf::from(TEMP_BUILD_DIR);
@@ -527,11 +319,8 @@ fn build_and_run_file(config: &Config, file_name: &str) -> Result<()> {
     let output_file = temp_dir.join(file_stem);
 
     let mut args = Vec::new();
-    let compiler = match config.settings.compiler {
-        Compiler::GCC => "gcc",
-        Compiler::CLANG => "clang",
-        Compiler::MSVC => "cl.exe",
-    };
+    let language = build::source_language(&source_file).unwrap_or(Language::C);
+    let compiler = build::compiler_driver(&config.settings.compiler, &language);
 
     match config.settings.compiler {
         Compiler::GCC | Compiler::CLANG => {
@@ -549,7 +338,7 @@ fn build_and_run_file(config: &Config, file_name: &str) -> Result<()> {
     let output = std::process::Command::new(compiler)
         .args(&args)
         .output()
-        .expect("Failed to execute build command");
+        .map_err(|e| Error::Config(format!("Failed to run the compiler `{}`: {}", compiler, e)))?;
 
     if !output.status.success() {
         std::io::stderr().write_all(&output.stderr)?;
@@ -558,88 +347,132 @@ fn build_and_run_file(config: &Config, file_name: &str) -> Result<()> {
 
     println!("Built file: {}", file_name);
 
-    log(config, &format!("Running: {}", output_file.display()));
-
-    let run_output = std::process::Command::new(&output_file)
-        .output()
-        .map_err(|e| Error::IO(e))?;
-
-    std::io::stdout().write_all(&run_output.stdout)?;
-    std::io::stderr().write_all(&run_output.stderr)?;
-
-    if !run_output.status.success() {
-        return Err(Error::RunFailed(run_output.status.code()));
-    }
-
+    let result = run_program(config, &output_file, program_args);
     std::fs::remove_file(output_file)?;
 
-    Ok(())
+    result
 }
 
-fn clean_project() -> Result<()> {
-    let bin_path = "bin";
-    if std::path::Path::new(bin_path).exists() {
+fn clean_project(config: &Config) -> Result<()> {
+    let bin_path = &config.target_dir;
+    if bin_path.exists() {
         std::fs::remove_dir_all(bin_path)?;
         println!("Cleaned build artifacts");
     }
     Ok(())
 }
 
-fn print_help() {
-    println!("Usage: cbuild <COMMAND>");
-    println!("\nCommands:");
-    println!("  new <NAME>    Create a new project");
-    println!("  build         Build the project");
-    println!("  run [FILE]    Build and run the project or a specific file");
-    println!("  clean         Remove build artifacts");
-    println!("  version       Print version info");
-    println!("  help          Print this help message");
-    println!("\nOptions:");
-    println!("  -v, --verbose Enable verbose output");
-}
-
 fn log(config: &Config, message: &str) {
     if config.verbose {
         println!("[cbuild] {}", message);
     }
 }
 
-fn main() -> Result<()> {
-    let args = parse_arguments()?;
+fn main() {
+    match parse_arguments().and_then(run_command) {
+        Ok(()) => {},
+        // The program already reported its failure, only pass its code on
+        Err(Error::RunFailed(code)) => std::process::exit(code),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        },
+    }
+}
 
-    let result = match args.command.as_str() {
-        "build" => build_project(args.config),
-        "new" => {
+fn run_command(args: Arguments) -> Result<()> {
+    match args.command.as_str() {
+        "build" => {
+            let workspace = Workspace::load(&args.config, &Update::Locked)?;
+            workspace.build(&workspace.select(args.package.as_deref())?).map(|_| ())
+        },
+        "new" => create_new_project(&args.config.project_name.unwrap()),
+        "run" => {
             if let Some(file) = args.file {
-                create_new_module(&file)
+                build_and_run_file(&args.config, &file, &args.program_args)
             }
             else {
-                create_new_project(&args.config.project_name.unwrap())
+                let workspace = Workspace::load(&args.config, &Update::Locked)?;
+                let package = workspace.runnable(args.package.as_deref())?;
+                check_runnable(&package.config)
+                    .and_then(|_| workspace.build(&[package]))
+                    .and_then(|_| run_project(&package.config, &args.program_args))
             }
         },
-        "run" => {
-            if let Some(file) = args.file {
-                build_and_run_file(&args.config, &file)
+        "test" => test_workspace(&args),
+        "compdb" => {
+            let workspace = Workspace::load(&args.config, &Update::Locked)?;
+            let imports = workspace.imports()?;
+            for package in workspace.select(args.package.as_deref())? {
+                build::generate_compile_commands(&package.config, &imports[package.name()])?;
             }
-            else {
-                build_project(args.config.clone()).and_then(|_| run_project(&args.config))
+            Ok(())
+        },
+        "update" => {
+            let update = match args.file {
+                Some(name) => Update::Packages(vec![name]),
+                None => Update::All,
+            };
+            workspace::update(&args.config, update)
+        },
+        "tree" => {
+            let workspace = Workspace::load(&args.config, &Update::Locked)?;
+            tree::print_tree(&workspace, &workspace.select(args.package.as_deref())?, &args.tree)
+        },
+        "add" => edit::add(&args.config, args.package.as_deref(), args.add.as_ref().unwrap()),
+        "remove" => edit::remove(&args.config, args.package.as_deref(), args.file.as_deref().unwrap()),
+        "vendor" => vendor::vendor(&args.config),
+        "package" | "publish" => {
+            let workspace = Workspace::load(&args.config, &Update::Locked)?;
+            for package in workspace.select(args.package.as_deref())? {
+                if args.command == "publish" {
+                    packaging::publish(&args.config, &package.config)?;
+                }
+                else {
+                    packaging::package(&package.config)?;
+                }
             }
+            Ok(())
         },
-        "clean" => clean_project(),
+        "clean" => clean_project(&args.config),
         "version" => {
             println!("cbuild version {}", VERSION);
             Ok(())
         },
-        "help" => {
-            print_help();
-            Ok(())
+        "help" => match args.file {
+            Some(name) => {
+                let command = cli::find_command(&name).ok_or_else(|| {
+                    Error::Arguments(format!("Unknown command `{}`", name))
+                })?;
+                cli::print_command_help(command);
+                Ok(())
+            },
+            None => {
+                cli::print_help();
+                Ok(())
+            },
         },
         _ => Err(Error::Arguments("Unknown command".to_string())),
-    };
+    }
+}
+
+/// Builds the selected packages and runs the tests of each of them
+fn test_workspace(args: &Arguments) -> Result<()> {
+    let workspace = Workspace::load(&args.config, &Update::Locked)?;
+    let selected = workspace.select(args.package.as_deref())?;
+    let outputs = workspace.build(&selected)?;
+
+    let mut failed = 0;
+    for package in selected {
+        let build = &outputs[package.name()];
+        match test_runner::test_project(&package.config, build, args.file.as_deref(), args.no_run) {
+            Err(Error::TestsFailed(count)) => failed += count,
+            result => result?,
+        }
+    }
 
-    if let Err(e) = result {
-        eprintln!("Error: {:?}", e);
-        std::process::exit(1);
+    if failed > 0 {
+        return Err(Error::TestsFailed(failed));
     }
 
     Ok(())
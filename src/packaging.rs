@@ -0,0 +1,274 @@
+use std::path::{Path, PathBuf};
+
+use semver::Version;
+
+use crate::archive;
+use crate::checksum;
+use crate::config::Config;
+use crate::dependency::Source;
+use crate::error::{Error, Result};
+use crate::lockfile::LOCKFILE;
+use crate::log;
+use crate::registry::{IndexEntry, Registry};
+
+/// Files and directories never packaged, besides the target directory
+const ALWAYS_EXCLUDED: &[&str] = &[".git", ".cbuild", "compile_commands.json", LOCKFILE];
+
+/// Archive of a project as it is published to a registry
+pub struct PackagedProject {
+    pub archive: PathBuf,
+    pub entry: IndexEntry,
+}
+
+/// Writes `bin/package/<name>-<version>.tar.gz` with the sources of the
+/// project of `config`, and its checksum next to it
+pub fn package(config: &Config) -> Result<PackagedProject> {
+    let version = validate(config)?;
+    let name = config.project_name.clone().unwrap();
+    let package = format!("{}-{}", name, version);
+
+    let files: Vec<(String, PathBuf)> = project_files(config)?.into_iter()
+        .map(|(relative, path)| (format!("{}/{}", package, relative), path))
+        .collect();
+
+    let output_dir = config.target_dir.join("package");
+    std::fs::create_dir_all(&output_dir)?;
+    let archive = output_dir.join(format!("{}.tar.gz", package));
+    log(config, &format!("Writing {} files to {}", files.len(), archive.display()));
+    archive::create(&archive, &files)?;
+
+    let checksum = checksum::sha256_file(&archive)?;
+    std::fs::write(output_dir.join(format!("{}.tar.gz.sha256", package)),
+        format!("{}  {}.tar.gz\n", checksum, package))?;
+    println!("Packaged `{}` {} ({} files) to {}", name, version, files.len(), archive.display());
+    println!("SHA-256: {}", checksum);
+
+    let dependencies = config.dependencies.iter()
+        .filter_map(|dependency| match &dependency.source {
+            Source::Registry(requirement) => Some((dependency.name.clone(), requirement.to_string())),
+            _ => None,
+        })
+        .collect();
+
+    Ok(PackagedProject {
+        archive,
+        entry: IndexEntry {
+            name,
+            version: version.to_string(),
+            dependencies,
+            checksum,
+        },
+    })
+}
+
+/// Packages the project of `config` and adds it to the registry of the
+/// workspace `root`
+pub fn publish(root: &Config, config: &Config) -> Result<()> {
+    let packaged = package(config)?;
+    let mut registry = Registry::new(&root.registry);
+    registry.publish(root, &packaged.entry, &packaged.archive)?;
+    println!("Published `{}` {} to {}", packaged.entry.name, packaged.entry.version, registry.location());
+    Ok(())
+}
+
+/// Checks that the manifest has everything a registry package needs,
+/// returning its version
+fn validate(config: &Config) -> Result<Version> {
+    let name = config.project_name.as_deref().unwrap_or_default();
+    let mut problems = Vec::new();
+
+    if name.is_empty() {
+        problems.push("`project.name` is missing".to_string());
+    }
+    else if !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+        problems.push(format!("`project.name` `{}` may only contain letters, digits, `_` and `-`", name));
+    }
+
+    let version = match config.project_version.as_deref() {
+        Some(version) => match Version::parse(version) {
+            Ok(version) => Some(version),
+            Err(e) => {
+                problems.push(format!("`project.version` `{}` is not a semantic version: {}", version, e));
+                None
+            },
+        },
+        None => {
+            problems.push("`project.version` is missing".to_string());
+            None
+        },
+    };
+
+    if config.project_license.as_deref().is_none_or(|license| license.trim().is_empty()) {
+        problems.push("`project.license` is missing".to_string());
+    }
+    if !config.root.join("include").is_dir() {
+        problems.push("the public headers must be in an `include/` directory".to_string());
+    }
+    if matches!(config.settings.build_type, crate::config::Type::Binary) {
+        problems.push("binaries cannot be published, only libraries".to_string());
+    }
+    for dependency in &config.dependencies {
+        if !matches!(dependency.source, Source::Registry(_)) {
+            problems.push(format!("dependency `{}` must come from the registry, not {}", dependency.name, dependency.source));
+        }
+    }
+
+    match version {
+        Some(version) if problems.is_empty() => Ok(version),
+        _ => Err(Error::Config(format!("Cannot package `{}`:\n  {}", name, problems.join("\n  ")))),
+    }
+}
+
+/// Files of the project, as paths relative to its root and on disk,
+/// leaving out build output and whatever `.gitignore` ignores
+fn project_files(config: &Config) -> Result<Vec<(String, PathBuf)>> {
+    let ignore = Ignore::load(&config.root)?;
+    let target_dir = config.target_dir.canonicalize().unwrap_or(config.target_dir.clone());
+
+    let mut files = Vec::new();
+    let mut directories = vec![config.root.clone()];
+    while let Some(current) = directories.pop() {
+        for entry in std::fs::read_dir(&current)? {
+            let path = entry?.path();
+            let relative = path.strip_prefix(&config.root).unwrap().to_string_lossy().replace('\\', "/");
+            let is_dir = path.is_dir();
+
+            if ALWAYS_EXCLUDED.iter().any(|excluded| *excluded == relative)
+                || path == target_dir
+                || config.vendor.as_ref() == Some(&path)
+                || ignore.is_ignored(&relative, is_dir) {
+                continue;
+            }
+            if is_dir {
+                directories.push(path);
+            }
+            else {
+                files.push((relative, path));
+            }
+        }
+    }
+
+    files.sort();
+    Ok(files)
+}
+
+/// Patterns of a `.gitignore` file in the project root
+struct Ignore {
+    patterns: Vec<IgnorePattern>,
+}
+
+struct IgnorePattern {
+    pattern: glob::Pattern,
+
+    /// `!pattern`, including paths an earlier pattern ignored
+    negated: bool,
+
+    /// `pattern/`, only matching directories
+    directory_only: bool,
+
+    /// Contains a `/`, so it is matched against the whole path instead of
+    /// the file name
+    anchored: bool,
+}
+
+impl Ignore {
+    fn load(root: &Path) -> Result<Self> {
+        let contents = match std::fs::read_to_string(root.join(".gitignore")) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Ignore::parse(&contents))
+    }
+
+    /// Patterns of a `.gitignore` file with `contents`
+    fn parse(contents: &str) -> Self {
+        let patterns = contents.lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .filter_map(|line| {
+                let (negated, line) = match line.strip_prefix('!') {
+                    Some(rest) => (true, rest),
+                    None => (false, line),
+                };
+                let (directory_only, line) = match line.strip_suffix('/') {
+                    Some(rest) => (true, rest),
+                    None => (false, line),
+                };
+                let anchored = line.contains('/');
+                let pattern = glob::Pattern::new(line.trim_start_matches('/')).ok()?;
+                Some(IgnorePattern { pattern, negated, directory_only, anchored })
+            })
+            .collect();
+
+        Ignore { patterns }
+    }
+
+    /// Whether `relative`, a path relative to the root, is ignored. Like
+    /// git, the last matching pattern decides.
+    fn is_ignored(&self, relative: &str, is_dir: bool) -> bool {
+        let file_name = relative.rsplit('/').next().unwrap_or(relative);
+        let options = glob::MatchOptions { require_literal_separator: true, ..glob::MatchOptions::new() };
+
+        let mut ignored = false;
+        for pattern in &self.patterns {
+            if pattern.directory_only && !is_dir {
+                continue;
+            }
+            let candidate = if pattern.anchored { relative } else { file_name };
+            if pattern.pattern.matches_with(candidate, options) {
+                ignored = !pattern.negated;
+            }
+        }
+        ignored
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unanchored_patterns_match_file_names_anywhere() {
+        let ignore = Ignore::parse("*.o\nbuild\n");
+        assert!(ignore.is_ignored("main.o", false));
+        assert!(ignore.is_ignored("src/util/main.o", false));
+        assert!(ignore.is_ignored("tools/build", true));
+        assert!(!ignore.is_ignored("src/main.c", false));
+    }
+
+    #[test]
+    fn patterns_with_a_slash_are_anchored_to_the_root() {
+        let ignore = Ignore::parse("/out\ndocs/*.html\n");
+        assert!(ignore.is_ignored("out", true));
+        assert!(!ignore.is_ignored("src/out", true));
+        assert!(ignore.is_ignored("docs/index.html", false));
+        assert!(!ignore.is_ignored("src/docs/index.html", false));
+        assert!(!ignore.is_ignored("docs/api/index.html", false));
+    }
+
+    #[test]
+    fn negated_patterns_include_paths_again() {
+        let ignore = Ignore::parse("*.h\n!public.h\n");
+        assert!(ignore.is_ignored("src/private.h", false));
+        assert!(!ignore.is_ignored("include/public.h", false));
+
+        // The last matching pattern decides
+        let ignore = Ignore::parse("!public.h\n*.h\n");
+        assert!(ignore.is_ignored("include/public.h", false));
+    }
+
+    #[test]
+    fn directory_patterns_only_match_directories() {
+        let ignore = Ignore::parse("cache/\n");
+        assert!(ignore.is_ignored("cache", true));
+        assert!(ignore.is_ignored("src/cache", true));
+        assert!(!ignore.is_ignored("cache", false));
+    }
+
+    #[test]
+    fn comments_and_blank_lines_are_skipped() {
+        let ignore = Ignore::parse("# *.c\n\n   \n");
+        assert!(!ignore.is_ignored("main.c", false));
+    }
+}
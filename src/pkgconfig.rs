@@ -0,0 +1,253 @@
+use std::path::PathBuf;
+
+use semver::Version;
+
+use crate::build::Exports;
+use crate::config::Config;
+use crate::dependency::SystemDependency;
+use crate::error::{Error, Result};
+use crate::log;
+
+/// Headers, libraries and flags of the system package `dependency`, as
+/// pkg-config reports them. `PKG_CONFIG_PATH` and the other variables
+/// pkg-config reads are passed on to it.
+pub fn probe(config: &Config, dependency: &SystemDependency) -> Result<Exports> {
+    let name = dependency.name.as_str();
+    if !run(config, &["--exists", name])?.status.success() {
+        let search_path = std::env::var("PKG_CONFIG_PATH").unwrap_or_default();
+        let searched = if search_path.is_empty() { String::new() } else { format!(" (PKG_CONFIG_PATH={})", search_path) };
+        return Err(Error::Library(format!(
+            "System package `{}` not found by pkg-config{}, install its development files or add the directory of its `{}.pc` to PKG_CONFIG_PATH",
+            name, searched, name)));
+    }
+
+    let found = pkg_config(config, &["--modversion", name])?;
+    let found = found.trim();
+    let version = parse_version(found).ok_or_else(|| {
+        Error::Library(format!("System package `{}` has version `{}`, which cbuild cannot compare", name, found))
+    })?;
+    if !dependency.requirement.matches(&version) {
+        return Err(Error::Library(format!("System package `{}` {} does not satisfy the requirement {}",
+            name, found, dependency.requirement)));
+    }
+
+    let mut exports = Exports::default();
+    for group in flag_groups(&pkg_config(config, &["--cflags", name])?) {
+        match include_dir(&group) {
+            Some(dir) => exports.include_dirs.push(PathBuf::from(dir)),
+            None => exports.compile_flags.push(group),
+        }
+    }
+
+    // `--static` adds what the package's own libraries need in turn
+    let own_groups = flag_groups(&pkg_config(config, &["--libs", name])?);
+    let own_libraries: Vec<&str> = own_groups.iter().filter_map(|group| library(group)).collect();
+    let groups = match dependency.static_link {
+        true => flag_groups(&pkg_config(config, &["--libs", "--static", name])?),
+        false => own_groups.clone(),
+    };
+
+    let mut library_dirs: Vec<PathBuf> = groups.iter().filter_map(|group| library_dir(group)).map(PathBuf::from).collect();
+    if dependency.static_link {
+        let libdir = PathBuf::from(pkg_config(config, &["--variable=libdir", name])?.trim());
+        if !libdir.as_os_str().is_empty() && !library_dirs.contains(&libdir) {
+            library_dirs.push(libdir);
+        }
+    }
+
+    for group in groups {
+        let Some(library) = library(&group) else {
+            exports.link_flags.push(group);
+            continue;
+        };
+        if !dependency.static_link {
+            exports.link_libraries.push(library.to_string());
+            continue;
+        }
+
+        // Static libraries are linked by path. The libraries they need in
+        // turn may still be shared system libraries like `m` or `dl`.
+        let archive = library_dirs.iter()
+            .map(|dir| dir.join(format!("lib{}.a", library)))
+            .find(|path| path.is_file());
+        match archive {
+            Some(archive) => exports.link_inputs.push(archive),
+            None if own_libraries.contains(&library) => {
+                return Err(Error::Library(format!("System package `{}` has no static library `lib{}.a` in {}",
+                    name, library, library_dirs.iter().map(|dir| dir.display().to_string()).collect::<Vec<_>>().join(", "))));
+            },
+            None => exports.link_libraries.push(library.to_string()),
+        }
+    }
+
+    log(config, &format!("Using system package `{}` {}", name, found));
+    Ok(exports)
+}
+
+/// Libraries that the `.pc` files in `dirs`, installed by a foreign build,
+/// list for static linking, every library before the ones it depends on
+pub fn installed_libraries(config: &Config, dirs: &[PathBuf]) -> Result<Vec<String>> {
+    let mut packages = Vec::new();
+    for dir in dirs {
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            continue;
+        };
+        for path in entries.flatten().map(|entry| entry.path()) {
+            if path.extension().is_some_and(|extension| extension == "pc") {
+                packages.push(path.file_stem().unwrap().to_string_lossy().to_string());
+            }
+        }
+    }
+    if packages.is_empty() {
+        return Ok(Vec::new());
+    }
+    packages.sort();
+
+    // The installed packages may require each other or system packages
+    let mut search_path = dirs.to_vec();
+    search_path.extend(std::env::var_os("PKG_CONFIG_PATH").iter().flat_map(std::env::split_paths));
+    let search_path = std::env::join_paths(search_path).map_err(|e| {
+        Error::Library(format!("Invalid PKG_CONFIG_PATH: {}", e))
+    })?;
+
+    let mut args = vec!["--libs", "--static"];
+    args.extend(packages.iter().map(String::as_str));
+    log(config, &format!("Running command: PKG_CONFIG_PATH={} pkg-config {}", search_path.to_string_lossy(), args.join(" ")));
+    let output = std::process::Command::new("pkg-config").args(&args).env("PKG_CONFIG_PATH", &search_path).output().map_err(|e| {
+        Error::Library(format!("Failed to run pkg-config: {}", e))
+    })?;
+    if !output.status.success() {
+        return Err(Error::Library(format!("pkg-config {} failed: {}",
+            args.join(" "), String::from_utf8_lossy(&output.stderr).trim())));
+    }
+
+    // A library required by several packages is listed after each of them,
+    // and the last place is the one that satisfies all
+    let mut libraries: Vec<String> = Vec::new();
+    for group in flag_groups(&String::from_utf8_lossy(&output.stdout)) {
+        if let Some(library) = library(&group) {
+            libraries.retain(|existing| existing != library);
+            libraries.push(library.to_string());
+        }
+    }
+    Ok(libraries)
+}
+
+/// Flags that take their argument as the next word
+const FLAGS_WITH_ARGUMENT: &[&str] = &[
+    "-I", "-L", "-l", "-D", "-U", "-isystem", "-idirafter", "-iquote", "-include", "-imacros",
+    "-framework", "-Xlinker", "-Xpreprocessor", "-arch",
+];
+
+/// Splits pkg-config output into flags, each followed by its argument when
+/// that is a separate word, so that `-isystem DIR` stays together
+fn flag_groups(output: &str) -> Vec<Vec<String>> {
+    let mut groups = Vec::new();
+    let mut words = output.split_whitespace();
+    while let Some(word) = words.next() {
+        let mut group = vec![word.to_string()];
+        if FLAGS_WITH_ARGUMENT.contains(&word) {
+            group.extend(words.next().map(str::to_string));
+        }
+        groups.push(group);
+    }
+    groups
+}
+
+/// Argument of `-IDIR` or `-I DIR`
+fn include_dir(group: &[String]) -> Option<&str> {
+    flag_argument(group, "-I")
+}
+
+/// Argument of `-LDIR` or `-L DIR`
+fn library_dir(group: &[String]) -> Option<&str> {
+    flag_argument(group, "-L")
+}
+
+/// Argument of `-lNAME` or `-l NAME`
+fn library(group: &[String]) -> Option<&str> {
+    flag_argument(group, "-l")
+}
+
+fn flag_argument<'a>(group: &'a [String], flag: &str) -> Option<&'a str> {
+    match group {
+        [single] => single.strip_prefix(flag).filter(|argument| !argument.is_empty()),
+        [first, argument] if first == flag => Some(argument),
+        _ => None,
+    }
+}
+
+/// Runs pkg-config, returning its standard output
+fn pkg_config(config: &Config, args: &[&str]) -> Result<String> {
+    let output = run(config, args)?;
+    if !output.status.success() {
+        return Err(Error::Library(format!("pkg-config {} failed: {}",
+            args.join(" "), String::from_utf8_lossy(&output.stderr).trim())));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+fn run(config: &Config, args: &[&str]) -> Result<std::process::Output> {
+    log(config, &format!("Running command: pkg-config {}", args.join(" ")));
+
+    std::process::Command::new("pkg-config").args(args).output().map_err(|e| {
+        Error::Library(format!("Failed to run pkg-config, which system dependencies are found with: {}", e))
+    })
+}
+
+/// Reads versions such as `3.40.1`, `1.2` or `1.2.11.1` as semantic
+/// versions, from their first three numbers
+fn parse_version(version: &str) -> Option<Version> {
+    let mut numbers = version.split(['.', '-', '+'])
+        .map_while(|part| part.parse::<u64>().ok());
+    let major = numbers.next()?;
+    Some(Version::new(major, numbers.next().unwrap_or(0), numbers.next().unwrap_or(0)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_versions_from_their_first_three_numbers() {
+        assert_eq!(parse_version("3.40.1"), Some(Version::new(3, 40, 1)));
+        assert_eq!(parse_version("1.2"), Some(Version::new(1, 2, 0)));
+        assert_eq!(parse_version("7"), Some(Version::new(7, 0, 0)));
+        assert_eq!(parse_version("1.2.11.1"), Some(Version::new(1, 2, 11)));
+        assert_eq!(parse_version("2.0.1-rc1"), Some(Version::new(2, 0, 1)));
+        assert_eq!(parse_version("1.6+git"), Some(Version::new(1, 6, 0)));
+        assert_eq!(parse_version("unknown"), None);
+        assert_eq!(parse_version(""), None);
+    }
+
+    #[test]
+    fn orders_installed_libraries_before_their_dependencies() {
+        let dir = std::env::temp_dir().join(format!("cbuild-test-pc-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("alpha.pc"), "Name: alpha\nDescription: a\nVersion: 1\nLibs: -lalpha\n").unwrap();
+        std::fs::write(dir.join("beta.pc"),
+            "Name: beta\nDescription: b\nVersion: 1\nRequires.private: alpha\nLibs: -lbeta\nLibs.private: -lm\n").unwrap();
+
+        let libraries = installed_libraries(&Config::default(), std::slice::from_ref(&dir));
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(libraries.unwrap(), ["beta", "m", "alpha"]);
+    }
+
+    #[test]
+    fn keeps_flags_with_their_arguments() {
+        let groups = flag_groups("-I/usr/include/a -isystem /opt/x/include -DFOO=1 -Xlinker --as-needed -lz\n");
+        assert_eq!(groups, [
+            vec!["-I/usr/include/a"],
+            vec!["-isystem", "/opt/x/include"],
+            vec!["-DFOO=1"],
+            vec!["-Xlinker", "--as-needed"],
+            vec!["-lz"],
+        ]);
+
+        let groups = flag_groups("-L /opt/lib -l ssl");
+        assert_eq!(library_dir(&groups[0]), Some("/opt/lib"));
+        assert_eq!(library(&groups[1]), Some("ssl"));
+        assert_eq!(include_dir(&groups[1]), None);
+    }
+}
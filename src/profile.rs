@@ -0,0 +1,286 @@
+use std::collections::BTreeMap;
+
+use serde::Deserialize;
+
+use crate::config::ConfigValue;
+use crate::diagnostic::closest_match;
+use crate::error::{Error, Result};
+
+/// Optimization level
+#[derive(Clone, Debug, PartialEq)]
+pub enum OptLevel {
+    /// No optimization (default for debug)
+    O0,
+    O1,
+    O2,
+    O3,
+
+    /// Optimize for size
+    Os,
+
+    /// Optimize aggressively for size
+    Oz,
+}
+
+impl ConfigValue for OptLevel {
+    const VALUES: &'static [(&'static str, Self)] = &[
+        ("0", OptLevel::O0),
+        ("1", OptLevel::O1),
+        ("2", OptLevel::O2),
+        ("3", OptLevel::O3),
+        ("s", OptLevel::Os),
+        ("z", OptLevel::Oz),
+    ];
+}
+
+/// Amount of debug information
+#[derive(Clone, Debug, PartialEq)]
+pub enum DebugInfo {
+    None,
+
+    /// Line tables only
+    Limited,
+
+    Full,
+}
+
+impl ConfigValue for DebugInfo {
+    const VALUES: &'static [(&'static str, Self)] = &[
+        ("0", DebugInfo::None),
+        ("1", DebugInfo::Limited),
+        ("2", DebugInfo::Full),
+        ("false", DebugInfo::None),
+        ("true", DebugInfo::Full),
+    ];
+}
+
+/// Resolved compiler and linker settings of a build profile
+#[derive(Clone, Debug)]
+pub struct Profile {
+    pub name: String,
+    pub opt_level: OptLevel,
+    pub debug: DebugInfo,
+
+    /// Remove symbols from the linked output
+    pub strip: bool,
+
+    /// Keep `assert` enabled, otherwise `NDEBUG` is defined
+    pub assertions: bool,
+
+    /// Extra compiler flags
+    pub flags: Vec<String>,
+
+    /// Extra linker flags
+    pub link_flags: Vec<String>,
+}
+
+impl Profile {
+    /// Profiles available without being declared in `config.toml`
+    pub const BUILTIN: &'static [&'static str] = &["debug", "release", "relwithdebinfo", "minsize"];
+
+    fn builtin(name: &str) -> Option<Self> {
+        let (opt_level, debug, strip, assertions) = match name {
+            "debug" => (OptLevel::O0, DebugInfo::Full, false, true),
+            "release" => (OptLevel::O3, DebugInfo::None, true, false),
+            "relwithdebinfo" => (OptLevel::O2, DebugInfo::Full, false, false),
+            "minsize" => (OptLevel::Os, DebugInfo::None, true, false),
+            _ => return None,
+        };
+
+        Some(Profile {
+            name: name.to_string(),
+            opt_level,
+            debug,
+            strip,
+            assertions,
+            flags: Vec::new(),
+            link_flags: Vec::new(),
+        })
+    }
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Profile::builtin("debug").unwrap()
+    }
+}
+
+/// Scalar accepted where `config.toml` allows either a number, a boolean
+/// or a string, such as `opt-level = 3` or `opt-level = "s"`
+#[derive(Clone, Debug, Deserialize)]
+#[serde(untagged)]
+pub enum Scalar {
+    Bool(bool),
+    Integer(i64),
+    String(String),
+}
+
+impl std::fmt::Display for Scalar {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Scalar::Bool(value) => write!(f, "{}", value),
+            Scalar::Integer(value) => write!(f, "{}", value),
+            Scalar::String(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+/// `[profile.<name>]` table
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct ProfileSection {
+    inherits: Option<String>,
+    opt_level: Option<Scalar>,
+    debug: Option<Scalar>,
+    strip: Option<bool>,
+    assertions: Option<bool>,
+    flags: Option<Vec<String>>,
+    link_flags: Option<Vec<String>>,
+}
+
+/// Resolves profile `name` from the built-in profiles and the `[profile]`
+/// tables of the config, following `inherits` chains
+pub fn resolve_profile(definitions: &BTreeMap<String, ProfileSection>, name: &str) -> Result<Profile> {
+    resolve(definitions, name, &mut Vec::new())
+}
+
+fn resolve(definitions: &BTreeMap<String, ProfileSection>, name: &str, chain: &mut Vec<String>) -> Result<Profile> {
+    if chain.iter().any(|seen| seen == name) {
+        chain.push(name.to_string());
+        return Err(Error::Config(format!("Profile inheritance cycle: {}", chain.join(" -> "))));
+    }
+    chain.push(name.to_string());
+
+    let definition = definitions.get(name);
+    let mut profile = match (definition.and_then(|d| d.inherits.as_deref()), Profile::builtin(name)) {
+        (Some(parent), _) => resolve(definitions, parent, chain)?,
+        (None, Some(builtin)) => builtin,
+        (None, None) if definition.is_some() => {
+            return Err(Error::Config(format!(
+                "Profile `{}` must specify which profile it inherits from (profile.{}.inherits)", name, name)));
+        },
+        (None, None) => {
+            let known: Vec<&str> = Profile::BUILTIN.iter().copied()
+                .chain(definitions.keys().map(String::as_str))
+                .collect();
+            let hint = closest_match(name, known.iter().copied())
+                .map(|candidate| format!(", did you mean `{}`?", candidate))
+                .unwrap_or_default();
+            return Err(Error::Config(format!("Unknown profile `{}`{}", name, hint)));
+        },
+    };
+    profile.name = name.to_string();
+
+    let Some(definition) = definition else {
+        return Ok(profile);
+    };
+
+    if let Some(value) = &definition.opt_level {
+        profile.opt_level = parse_scalar(name, "opt-level", value)?;
+    }
+    if let Some(value) = &definition.debug {
+        profile.debug = parse_scalar(name, "debug", value)?;
+    }
+    if let Some(strip) = definition.strip {
+        profile.strip = strip;
+    }
+    if let Some(assertions) = definition.assertions {
+        profile.assertions = assertions;
+    }
+    if let Some(flags) = &definition.flags {
+        profile.flags.extend(flags.iter().cloned());
+    }
+    if let Some(flags) = &definition.link_flags {
+        profile.link_flags.extend(flags.iter().cloned());
+    }
+
+    Ok(profile)
+}
+
+fn parse_scalar<T: ConfigValue>(profile: &str, key: &str, value: &Scalar) -> Result<T> {
+    let value = value.to_string();
+    T::from_config(&value).ok_or_else(|| {
+        Error::Config(format!("Unsupported value `{}` for key `profile.{}.{}`", value, profile, key))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn definitions(toml: &str) -> BTreeMap<String, ProfileSection> {
+        toml::from_str(toml).unwrap()
+    }
+
+    #[test]
+    fn resolves_builtin_profiles() {
+        let profile = resolve_profile(&BTreeMap::new(), "release").unwrap();
+        assert_eq!(profile.opt_level, OptLevel::O3);
+        assert_eq!(profile.debug, DebugInfo::None);
+        assert!(profile.strip);
+        assert!(!profile.assertions);
+    }
+
+    #[test]
+    fn overrides_builtin_profiles() {
+        let definitions = definitions("[release]\ndebug = 1\nflags = [\"-march=native\"]\n");
+        let profile = resolve_profile(&definitions, "release").unwrap();
+        assert_eq!(profile.opt_level, OptLevel::O3);
+        assert_eq!(profile.debug, DebugInfo::Limited);
+        assert_eq!(profile.flags, ["-march=native"]);
+    }
+
+    #[test]
+    fn follows_inherits_chains() {
+        let definitions = definitions(r#"
+            [profiling]
+            inherits = "release"
+            debug = true
+            strip = false
+            flags = ["-pg"]
+
+            [bench]
+            inherits = "profiling"
+            opt-level = "s"
+            flags = ["-DBENCH"]
+            link-flags = ["-pg"]
+        "#);
+        let profile = resolve_profile(&definitions, "bench").unwrap();
+        assert_eq!(profile.name, "bench");
+        assert_eq!(profile.opt_level, OptLevel::Os);
+        assert_eq!(profile.debug, DebugInfo::Full);
+        assert!(!profile.strip);
+        assert!(!profile.assertions);
+        assert_eq!(profile.flags, ["-pg", "-DBENCH"]);
+        assert_eq!(profile.link_flags, ["-pg"]);
+    }
+
+    #[test]
+    fn reports_inheritance_cycles() {
+        let definitions = definitions("[a]\ninherits = \"b\"\n[b]\ninherits = \"a\"\n");
+        let error = resolve_profile(&definitions, "a").unwrap_err();
+        assert_eq!(error.to_string(), "Configuration error: Profile inheritance cycle: a -> b -> a");
+    }
+
+    #[test]
+    fn requires_inherits_for_custom_profiles() {
+        let definitions = definitions("[fast]\nopt-level = 3\n");
+        let error = resolve_profile(&definitions, "fast").unwrap_err();
+        assert_eq!(error.to_string(),
+            "Configuration error: Profile `fast` must specify which profile it inherits from (profile.fast.inherits)");
+    }
+
+    #[test]
+    fn reports_unknown_profiles() {
+        let definitions = definitions("[fast]\ninherits = \"relase\"\n");
+        let error = resolve_profile(&definitions, "fast").unwrap_err();
+        assert_eq!(error.to_string(), "Configuration error: Unknown profile `relase`, did you mean `release`?");
+    }
+
+    #[test]
+    fn rejects_unsupported_values() {
+        let definitions = definitions("[release]\nopt-level = 4\n");
+        let error = resolve_profile(&definitions, "release").unwrap_err();
+        assert_eq!(error.to_string(), "Configuration error: Unsupported value `4` for key `profile.release.opt-level`");
+    }
+}
@@ -0,0 +1,483 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use semver::{Version, VersionReq};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::archive;
+use crate::checksum;
+use crate::config::{self, Config};
+use crate::error::{Error, Result};
+
+/// Registry used when neither `[registry] url` nor `CBUILD_REGISTRY` is set
+pub const DEFAULT_REGISTRY: &str = "~/.cbuild/registry/";
+
+/// Downloaded archives and their extracted sources, per registry
+const REGISTRY_CACHE_PATH: &str = "~/.cbuild/registry-cache/";
+
+/// Marks an extracted package as complete, so that interrupted extractions
+/// are redone
+const EXTRACT_READY: &str = ".cbuild-ready";
+
+/// Line of `index/<name>` describing one published version of a package
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct IndexEntry {
+    pub name: String,
+    pub version: String,
+
+    /// Registry packages it depends on, with their version requirements
+    #[serde(default)]
+    pub dependencies: BTreeMap<String, String>,
+
+    /// SHA-256 of the package archive
+    pub checksum: String,
+}
+
+/// Version requirement on a registry package
+#[derive(Clone, Debug)]
+pub struct Requirement {
+    pub name: String,
+    pub requirement: VersionReq,
+
+    /// Package placing the requirement, for conflict reports
+    pub required_by: String,
+}
+
+/// A package registry: an `index/` directory with one file per package,
+/// listing its versions as JSON lines, and the archives themselves in
+/// `packages/<name>/<name>-<version>.tar.gz`
+pub struct Registry {
+    location: String,
+
+    /// Versions of every package read so far, oldest first
+    index: BTreeMap<String, Vec<(Version, IndexEntry)>>,
+}
+
+impl Registry {
+    pub fn new(location: &str) -> Self {
+        let location = if config::is_url(location) {
+            location.trim_end_matches('/').to_string()
+        }
+        else {
+            shellexpand::tilde(location).trim_end_matches(['/', '\\']).to_string()
+        };
+
+        Registry { location, index: BTreeMap::new() }
+    }
+
+    pub fn location(&self) -> &str {
+        &self.location
+    }
+
+    /// Lockfile source of packages from this registry
+    pub fn source(&self) -> String {
+        format!("registry+{}", self.location)
+    }
+
+    /// Published versions of `name`, oldest first, or `None` when the
+    /// registry doesn't have the package
+    pub fn versions(&mut self, config: &Config, name: &str) -> Result<Option<&[(Version, IndexEntry)]>> {
+        if !self.index.contains_key(name) {
+            let Some(contents) = self.read(config, &format!("index/{}", name))? else {
+                return Ok(None);
+            };
+
+            let mut versions = Vec::new();
+            for line in String::from_utf8_lossy(&contents).lines().filter(|line| !line.trim().is_empty()) {
+                let entry: IndexEntry = serde_json::from_str(line).map_err(|e| {
+                    Error::Library(format!("Invalid index entry for `{}` in registry {}: {}", name, self.location, e))
+                })?;
+                let version = Version::parse(&entry.version).map_err(|e| {
+                    Error::Library(format!("Invalid version `{}` of `{}` in registry {}: {}", entry.version, name, self.location, e))
+                })?;
+                versions.push((version, entry));
+            }
+            versions.sort_by(|a, b| a.0.cmp(&b.0));
+            self.index.insert(name.to_string(), versions);
+        }
+
+        Ok(Some(&self.index[name]))
+    }
+
+    /// Directory with the extracted sources of `entry`, downloading and
+    /// verifying its archive when it isn't in the cache yet
+    pub fn download(&self, config: &Config, entry: &IndexEntry) -> Result<PathBuf> {
+        let package = format!("{}-{}", entry.name, entry.version);
+        let cache = cache_dir(&self.location);
+        let dir = cache.join("src").join(&package);
+        if dir.join(EXTRACT_READY).exists() {
+            return Ok(dir);
+        }
+
+        let archive_path = format!("packages/{}/{}.tar.gz", entry.name, package);
+        let archive = if config::is_url(&self.location) {
+            println!("Downloading {} {}", entry.name, entry.version);
+            let contents = self.read(config, &archive_path)?.ok_or_else(|| {
+                Error::Library(format!("Archive of `{}` {} missing from registry {}", entry.name, entry.version, self.location))
+            })?;
+            let archive = cache.join("archives").join(format!("{}.tar.gz", package));
+            std::fs::create_dir_all(archive.parent().unwrap())?;
+            std::fs::write(&archive, contents)?;
+            archive
+        }
+        else {
+            PathBuf::from(&self.location).join(archive_path)
+        };
+
+        if !archive.is_file() {
+            return Err(Error::Library(format!("Archive of `{}` {} missing from registry {}", entry.name, entry.version, self.location)));
+        }
+        let found = checksum::sha256_file(&archive)?;
+        if found != entry.checksum {
+            return Err(Error::Library(format!(
+                "Checksum of `{}` {} does not match the registry index (expected {}, found {})",
+                entry.name, entry.version, entry.checksum, found)));
+        }
+
+        println!("Unpacking {} {}", entry.name, entry.version);
+        if dir.exists() {
+            std::fs::remove_dir_all(&dir)?;
+        }
+        std::fs::create_dir_all(dir.parent().unwrap())?;
+        archive::extract(&archive, dir.parent().unwrap())?;
+        if !dir.is_dir() {
+            return Err(Error::Library(format!("Archive of `{}` {} does not contain a `{}/` directory", entry.name, entry.version, package)));
+        }
+        std::fs::write(dir.join(EXTRACT_READY), "")?;
+
+        Ok(dir)
+    }
+
+    /// Adds `archive` to the registry as the new version `entry`, refusing
+    /// to replace a version that is already published
+    pub fn publish(&mut self, config: &Config, entry: &IndexEntry, archive: &Path) -> Result<()> {
+        if config::is_url(&self.location) {
+            return Err(Error::Library(format!(
+                "Cannot publish to {}, publish to the directory it serves instead", self.location)));
+        }
+
+        let published = self.versions(config, &entry.name)?
+            .is_some_and(|versions| versions.iter().any(|(_, published)| published.version == entry.version));
+        let target = PathBuf::from(&self.location)
+            .join("packages")
+            .join(&entry.name)
+            .join(format!("{}-{}.tar.gz", entry.name, entry.version));
+        if published || target.exists() {
+            return Err(Error::Library(format!("`{}` {} is already published in registry {}",
+                entry.name, entry.version, self.location)));
+        }
+
+        std::fs::create_dir_all(target.parent().unwrap())?;
+        std::fs::copy(archive, &target)?;
+
+        let index = PathBuf::from(&self.location).join("index").join(&entry.name);
+        std::fs::create_dir_all(index.parent().unwrap())?;
+        let line = serde_json::to_string(entry).map_err(|e| Error::Library(e.to_string()))?;
+        let mut file = std::fs::OpenOptions::new().create(true).append(true).open(&index)?;
+        writeln!(file, "{}", line)?;
+
+        self.index.remove(&entry.name);
+        Ok(())
+    }
+
+    /// Contents of the file at `path` in the registry, `None` when it
+    /// doesn't exist
+    fn read(&self, config: &Config, path: &str) -> Result<Option<Vec<u8>>> {
+        if !config::is_url(&self.location) {
+            let file = PathBuf::from(&self.location).join(path);
+            return match std::fs::read(&file) {
+                Ok(contents) => Ok(Some(contents)),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+                Err(e) => Err(e.into()),
+            };
+        }
+
+        let url = format!("{}/{}", self.location, path);
+        let output_file = cache_dir(&self.location).join("download.tmp");
+        std::fs::create_dir_all(output_file.parent().unwrap())?;
+        if !archive::download(config, &url, &output_file)? {
+            return Ok(None);
+        }
+
+        let contents = std::fs::read(&output_file)?;
+        std::fs::remove_file(&output_file)?;
+        Ok(Some(contents))
+    }
+}
+
+/// Picks one version of every package reachable from `requirements` so that
+/// all requirements hold, preferring the versions in `preferred` and
+/// otherwise the highest ones. Packages in `selected` keep their version
+/// and are not returned.
+pub fn resolve(
+    registry: &mut Registry,
+    config: &Config,
+    requirements: &[Requirement],
+    preferred: &BTreeMap<String, Version>,
+    selected: &BTreeMap<String, Version>,
+) -> Result<BTreeMap<String, IndexEntry>> {
+    // Read every package that could take part first, so that the search
+    // itself can't fail on I/O
+    let mut pending: Vec<String> = requirements.iter().map(|requirement| requirement.name.clone()).collect();
+    let mut index = BTreeMap::new();
+    while let Some(name) = pending.pop() {
+        if index.contains_key(&name) {
+            continue;
+        }
+        let Some(versions) = registry.versions(config, &name)? else {
+            let required_by = requirements.iter()
+                .find(|requirement| requirement.name == name)
+                .map(|requirement| requirement.required_by.clone())
+                .or_else(|| index.values().flatten()
+                    .find(|(_, entry): &&(Version, IndexEntry)| entry.dependencies.contains_key(&name))
+                    .map(|(version, entry)| format!("{} {}", entry.name, version)))
+                .unwrap_or_default();
+            return Err(Error::Library(format!("Package `{}` not found in registry {} (required by `{}`)",
+                name, registry.location, required_by)));
+        };
+        let versions = versions.to_vec();
+        for (_, entry) in &versions {
+            pending.extend(entry.dependencies.keys().cloned());
+        }
+        index.insert(name, versions);
+    }
+
+    let resolution = Resolution { index: &index, preferred };
+    let solution = resolution.solve(requirements.to_vec(), selected.clone())
+        .map_err(Error::Library)?;
+
+    Ok(solution.into_iter()
+        .filter(|(name, _)| !selected.contains_key(name))
+        .map(|(name, version)| {
+            let entry = index[&name].iter().find(|(candidate, _)| *candidate == version).unwrap().1.clone();
+            (name, entry)
+        })
+        .collect())
+}
+
+/// Backtracking search over the registry index
+struct Resolution<'a> {
+    index: &'a BTreeMap<String, Vec<(Version, IndexEntry)>>,
+    preferred: &'a BTreeMap<String, Version>,
+}
+
+impl Resolution<'_> {
+    /// Selects a version for the first package that has none yet and
+    /// recurses, trying the next candidate when that fails. The error is
+    /// the conflict found with the most preferred candidate.
+    fn solve(&self, requirements: Vec<Requirement>, selected: BTreeMap<String, Version>) -> std::result::Result<BTreeMap<String, Version>, String> {
+        let Some(next) = requirements.iter().find(|requirement| !selected.contains_key(&requirement.name)) else {
+            return Ok(selected);
+        };
+        let name = next.name.clone();
+
+        let constraints: Vec<&Requirement> = requirements.iter().filter(|requirement| requirement.name == name).collect();
+        let mut candidates: Vec<&(Version, IndexEntry)> = self.index[&name].iter().rev()
+            .filter(|(version, _)| constraints.iter().all(|constraint| constraint.requirement.matches(version)))
+            .collect();
+        if let Some(preferred) = self.preferred.get(&name) {
+            candidates.sort_by_key(|(version, _)| version != preferred);
+        }
+        if candidates.is_empty() {
+            return Err(self.conflict(&name, &requirements));
+        }
+
+        let mut first_error = None;
+        for (version, entry) in candidates {
+            let mut requirements = requirements.clone();
+            for (dependency, requirement) in &entry.dependencies {
+                let requirement = VersionReq::parse(requirement).map_err(|e| {
+                    format!("Invalid requirement `{}` on `{}` in `{}` {}: {}", requirement, dependency, name, version, e)
+                })?;
+                requirements.push(Requirement {
+                    name: dependency.clone(),
+                    requirement,
+                    required_by: format!("{} {}", name, version),
+                });
+            }
+
+            // A new requirement may rule out a version selected earlier
+            let broken = requirements.iter().find(|requirement| {
+                selected.get(&requirement.name).is_some_and(|version| !requirement.requirement.matches(version))
+            });
+            let result = match broken {
+                Some(requirement) => Err(self.conflict(&requirement.name, &requirements)),
+                None => {
+                    let mut selected = selected.clone();
+                    selected.insert(name.clone(), version.clone());
+                    self.solve(requirements, selected)
+                },
+            };
+
+            match result {
+                Ok(selected) => return Ok(selected),
+                Err(e) => {
+                    first_error.get_or_insert(e);
+                },
+            }
+        }
+
+        Err(first_error.unwrap())
+    }
+
+    /// Explains why no version of `name` satisfies `requirements`
+    fn conflict(&self, name: &str, requirements: &[Requirement]) -> String {
+        let mut message = format!("No version of `{}` satisfies all requirements:", name);
+        for requirement in requirements.iter().filter(|requirement| requirement.name == name) {
+            message.push_str(&format!("\n  {} (required by `{}`)", requirement.requirement, requirement.required_by));
+        }
+
+        let available: Vec<String> = self.index[name].iter().map(|(version, _)| version.to_string()).collect();
+        if available.is_empty() {
+            message.push_str("\nThe registry has no versions of it");
+        }
+        else {
+            message.push_str(&format!("\nAvailable versions: {}", available.join(", ")));
+        }
+        message
+    }
+}
+
+/// Cache directory of the registry at `location`, such as
+/// `registry-1a2b3c4d5e6f7a8b`
+fn cache_dir(location: &str) -> PathBuf {
+    let last = location.trim_end_matches(['/', '\\']).rsplit(['/', '\\']).next().unwrap_or_default().replace(':', "-");
+    let hash = Sha256::digest(location.as_bytes());
+    PathBuf::from(shellexpand::tilde(REGISTRY_CACHE_PATH).to_string())
+        .join(format!("{}-{}", last, checksum::hex(&hash[..8])))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Index = BTreeMap<String, Vec<(Version, IndexEntry)>>;
+
+    /// Name, version and dependencies of a published version
+    type Published<'a> = (&'a str, &'a str, &'a [(&'a str, &'a str)]);
+
+    fn index(packages: &[Published]) -> Index {
+        let mut index = Index::new();
+        for (name, version, dependencies) in packages {
+            index.entry(name.to_string()).or_default().push((Version::parse(version).unwrap(), IndexEntry {
+                name: name.to_string(),
+                version: version.to_string(),
+                dependencies: dependencies.iter().map(|(name, requirement)| (name.to_string(), requirement.to_string())).collect(),
+                checksum: String::new(),
+            }));
+        }
+        index
+    }
+
+    fn requirement(name: &str, requirement: &str) -> Requirement {
+        Requirement {
+            name: name.to_string(),
+            requirement: VersionReq::parse(requirement).unwrap(),
+            required_by: "app".to_string(),
+        }
+    }
+
+    fn solve(index: &Index, preferred: &BTreeMap<String, Version>, requirements: &[Requirement]) -> std::result::Result<Vec<String>, String> {
+        let resolution = Resolution { index, preferred };
+        let selected = resolution.solve(requirements.to_vec(), BTreeMap::new())?;
+        Ok(selected.iter().map(|(name, version)| format!("{} {}", name, version)).collect())
+    }
+
+    #[test]
+    fn selects_highest_matching_versions() {
+        let index = index(&[
+            ("a", "1.0.0", &[("b", "^1.0")]),
+            ("a", "1.2.0", &[("b", "^1.1")]),
+            ("a", "2.0.0", &[]),
+            ("b", "1.0.0", &[]),
+            ("b", "1.3.0", &[]),
+        ]);
+        let selected = solve(&index, &BTreeMap::new(), &[requirement("a", "^1")]);
+        assert_eq!(selected.unwrap(), ["a 1.2.0", "b 1.3.0"]);
+    }
+
+    #[test]
+    fn prefers_locked_versions() {
+        let index = index(&[
+            ("a", "1.0.0", &[]),
+            ("a", "1.1.0", &[]),
+            ("a", "1.2.0", &[]),
+        ]);
+        let preferred = BTreeMap::from([("a".to_string(), Version::new(1, 1, 0))]);
+        let selected = solve(&index, &preferred, &[requirement("a", "^1")]);
+        assert_eq!(selected.unwrap(), ["a 1.1.0"]);
+
+        // A locked version the requirements no longer allow is replaced
+        let selected = solve(&index, &preferred, &[requirement("a", ">=1.2")]);
+        assert_eq!(selected.unwrap(), ["a 1.2.0"]);
+    }
+
+    #[test]
+    fn backtracks_when_a_dependency_cannot_be_satisfied() {
+        let index = index(&[
+            ("a", "1.0.0", &[("b", "^1.0")]),
+            ("a", "1.1.0", &[("b", "^2.0")]),
+            ("b", "1.0.0", &[]),
+            ("b", "2.0.0", &[]),
+        ]);
+        let selected = solve(&index, &BTreeMap::new(), &[requirement("a", "^1"), requirement("b", "^1")]);
+        assert_eq!(selected.unwrap(), ["a 1.0.0", "b 1.0.0"]);
+    }
+
+    #[test]
+    fn backtracks_when_a_dependency_rules_out_a_selected_version() {
+        let index = index(&[
+            ("a", "1.0.0", &[("b", "^1.0")]),
+            ("a", "2.0.0", &[("b", "^2.0")]),
+            ("b", "1.0.0", &[]),
+            ("b", "2.0.0", &[]),
+        ]);
+        let selected = solve(&index, &BTreeMap::new(), &[requirement("b", "^1"), requirement("a", "*")]);
+        assert_eq!(selected.unwrap(), ["a 1.0.0", "b 1.0.0"]);
+    }
+
+    #[test]
+    fn keeps_versions_selected_earlier() {
+        let index = index(&[
+            ("a", "1.0.0", &[]),
+            ("a", "1.1.0", &[]),
+            ("c", "1.0.0", &[("a", "^1.1")]),
+            ("c", "2.0.0", &[("a", "^1.0")]),
+        ]);
+        let preferred = BTreeMap::new();
+        let resolution = Resolution { index: &index, preferred: &preferred };
+        let selected = BTreeMap::from([("a".to_string(), Version::new(1, 0, 0))]);
+
+        let solution = resolution.solve(vec![requirement("a", "^1"), requirement("c", "*")], selected.clone()).unwrap();
+        assert_eq!(solution["a"], Version::new(1, 0, 0));
+        assert_eq!(solution["c"], Version::new(2, 0, 0));
+
+        let error = resolution.solve(vec![requirement("a", "^1"), requirement("c", "^1")], selected).unwrap_err();
+        assert!(error.starts_with("No version of `a` satisfies all requirements:"), "{}", error);
+    }
+
+    #[test]
+    fn reports_the_conflicting_requirements() {
+        let index = index(&[
+            ("a", "1.0.0", &[("b", "^2.0")]),
+            ("b", "1.0.0", &[]),
+            ("b", "2.0.0", &[]),
+        ]);
+        let error = solve(&index, &BTreeMap::new(), &[requirement("a", "^1"), requirement("b", "^1")]).unwrap_err();
+        assert_eq!(error, "No version of `b` satisfies all requirements:\n  \
+            ^1 (required by `app`)\n  \
+            ^2.0 (required by `a 1.0.0`)\n\
+            Available versions: 1.0.0, 2.0.0");
+    }
+
+    #[test]
+    fn reports_when_no_version_matches() {
+        let index = index(&[("a", "1.0.0", &[])]);
+        let error = solve(&index, &BTreeMap::new(), &[requirement("a", "^2")]).unwrap_err();
+        assert_eq!(error, "No version of `a` satisfies all requirements:\n  \
+            ^2 (required by `app`)\n\
+            Available versions: 1.0.0");
+    }
+}
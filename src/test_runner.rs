@@ -0,0 +1,160 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::build::{self, BuildOutput, CompileUnit, LinkStep};
+use crate::config::{Config, Language, Type};
+use crate::error::{Error, Result};
+use crate::log;
+
+/// Directory holding one test program per source file
+const TESTS_DIR: &str = "tests";
+
+/// Builds every test in `tests/` whose name contains `filter` against the
+/// already built project and, unless `no_run` is set, runs them and reports
+/// the results
+pub fn test_project(config: &Config, build: &BuildOutput, filter: Option<&str>, no_run: bool) -> Result<()> {
+    let root = &config.root;
+    let project_name = config.project_name.as_deref().unwrap_or_default();
+    let tests_path = build::profile_dir(config).join(TESTS_DIR).join(project_name);
+    let obj_path = build::object_dir(config);
+
+    let test_sources = discover_tests(&root.join(TESTS_DIR), filter)?;
+    if test_sources.is_empty() {
+        println!("No tests found in {}", root.join(TESTS_DIR).display());
+        return Ok(());
+    }
+
+    // Tests may include the project's own headers, even private ones next
+    // to its sources
+    let mut include_dirs = vec![root.join("lib"), build::public_include_dir(config), root.join("src")];
+    include_dirs.dedup();
+    include_dirs.extend(build.imports.include_dirs.iter().cloned());
+    let units = build::plan_units(config, root, &obj_path, test_sources, &include_dirs, &build.imports.compile_flags.concat());
+    build::compile_stale(config, &units, &build.compiler_version)?;
+
+    let mut project_inputs = project_link_inputs(config, build);
+    project_inputs.extend(build.imports.link_inputs.iter().cloned());
+    let project_has_cpp = build.imports.needs_cpp || build.units.iter().any(|unit| matches!(unit.language, Language::CPP));
+
+    let mut tests = Vec::new();
+    for unit in &units {
+        let name = test_name(unit);
+        let executable = tests_path.join(&name);
+
+        let mut inputs = vec![unit.object.clone()];
+        inputs.extend(project_inputs.iter().cloned());
+
+        let link = LinkStep {
+            build_type: &Type::Binary,
+            inputs,
+            libraries: build::link_libraries(config, &build.imports),
+            flags: build.imports.link_flags.concat(),
+            has_cpp: project_has_cpp || matches!(unit.language, Language::CPP),
+            output: executable.clone(),
+            cmd_file: obj_path.join(TESTS_DIR).join(format!("{}.link.cmd", name)),
+        };
+        link.run(config, &build.compiler_version, false)?;
+
+        tests.push((name, executable));
+    }
+
+    if no_run {
+        println!("Built {} tests", tests.len());
+        return Ok(());
+    }
+
+    run_tests(config, root, &tests)
+}
+
+/// Test sources directly inside `tests_dir` whose name contains `filter`
+fn discover_tests(tests_dir: &Path, filter: Option<&str>) -> Result<Vec<(PathBuf, Language)>> {
+    let mut sources = Vec::new();
+    if !tests_dir.is_dir() {
+        return Ok(sources);
+    }
+
+    for entry in std::fs::read_dir(tests_dir)? {
+        let path = entry?.path();
+        if !path.is_file() {
+            continue;
+        }
+
+        let Some(language) = build::source_language(&path) else {
+            continue;
+        };
+        let stem = path.file_stem().unwrap().to_string_lossy();
+        if filter.is_some_and(|filter| !stem.contains(filter)) {
+            continue;
+        }
+
+        sources.push((path, language));
+    }
+
+    sources.sort_by(|(a, _), (b, _)| a.cmp(b));
+    Ok(sources)
+}
+
+fn test_name(unit: &CompileUnit) -> String {
+    unit.source.file_stem().unwrap().to_string_lossy().to_string()
+}
+
+/// What every test executable is linked against: the static library for
+/// `lib` projects, otherwise every project object except the one defining
+/// `main`
+fn project_link_inputs(config: &Config, build: &build::BuildOutput) -> Vec<PathBuf> {
+    match config.settings.build_type {
+        Type::Library => vec![build.output_file.clone()],
+        Type::Binary | Type::DynLibrary => build.units.iter()
+            .filter(|unit| unit.source.file_stem().is_none_or(|stem| stem != "main"))
+            .map(|unit| unit.object.clone())
+            .collect(),
+    }
+}
+
+fn run_tests(config: &Config, root: &Path, tests: &[(String, PathBuf)]) -> Result<()> {
+    println!("\nrunning {} tests", tests.len());
+
+    let started = Instant::now();
+    let mut failed = Vec::new();
+    for (name, executable) in tests {
+        log(config, &format!("Running: {}", executable.display()));
+
+        let test_started = Instant::now();
+        let output = std::process::Command::new(executable)
+            .current_dir(root)
+            .output()?;
+        let elapsed = test_started.elapsed();
+
+        if output.status.success() {
+            println!("test {} ... ok ({})", name, format_duration(elapsed));
+        }
+        else {
+            println!("test {} ... FAILED ({})", name, format_duration(elapsed));
+            failed.push((name, output));
+        }
+    }
+
+    for (name, output) in &failed {
+        println!("\n---- {} (exit code: {:?}) ----", name, output.status.code());
+        std::io::stdout().write_all(&output.stdout)?;
+        std::io::stdout().write_all(&output.stderr)?;
+    }
+
+    println!("\ntest result: {}. {} passed; {} failed; finished in {}",
+        if failed.is_empty() { "ok" } else { "FAILED" },
+        tests.len() - failed.len(),
+        failed.len(),
+        format_duration(started.elapsed()));
+
+    if failed.is_empty() {
+        Ok(())
+    }
+    else {
+        Err(Error::TestsFailed(failed.len()))
+    }
+}
+
+fn format_duration(duration: Duration) -> String {
+    format!("{:.2}s", duration.as_secs_f64())
+}
@@ -0,0 +1,181 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::error::{Error, Result};
+use crate::workspace::{Package, Workspace};
+
+/// Output format of `cbuild tree`
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Format {
+    #[default]
+    Text,
+
+    /// Graphviz graph
+    Dot,
+
+    Json,
+}
+
+impl Format {
+    pub fn parse(value: &str) -> Result<Self> {
+        match value.to_lowercase().as_str() {
+            "text" => Ok(Format::Text),
+            "dot" => Ok(Format::Dot),
+            "json" => Ok(Format::Json),
+            _ => Err(Error::Arguments(format!("Unsupported format `{}`, expected one of: text, dot, json", value))),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct TreeOptions {
+    /// Show the packages depending on this one instead
+    pub invert: Option<String>,
+
+    pub format: Format,
+}
+
+/// Prints the dependency graph below `selected`, or above the package to
+/// invert
+pub fn print_tree(workspace: &Workspace, selected: &[&Package], options: &TreeOptions) -> Result<()> {
+    let mut edges: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+    for package in &workspace.packages {
+        edges.entry(package.name()).or_default();
+        for dependency in &package.dependencies {
+            match options.invert {
+                Some(_) => edges.entry(dependency).or_default().push(package.name()),
+                None => edges.entry(package.name()).or_default().push(dependency),
+            }
+        }
+    }
+    for children in edges.values_mut() {
+        children.sort();
+    }
+
+    let roots: Vec<&str> = match &options.invert {
+        Some(name) => vec![workspace.package(name)?.name()],
+        None => selected.iter().map(|package| package.name()).collect(),
+    };
+    let graph = Graph { workspace, edges, inverted: options.invert.is_some() };
+
+    match options.format {
+        Format::Text => graph.print_text(&roots),
+        Format::Dot => graph.print_dot(&roots),
+        Format::Json => graph.print_json(&roots)?,
+    }
+    Ok(())
+}
+
+struct Graph<'a> {
+    workspace: &'a Workspace,
+
+    /// Dependencies of every package, or its dependents when inverted
+    edges: BTreeMap<&'a str, Vec<&'a str>>,
+
+    inverted: bool,
+}
+
+impl Graph<'_> {
+    fn package(&self, name: &str) -> &Package {
+        self.workspace.packages.iter().find(|package| package.name() == name).unwrap()
+    }
+
+    /// `name v1.2.0 (source)`
+    fn label(&self, name: &str) -> String {
+        let package = self.package(name);
+        match &package.version {
+            Some(version) => format!("{} v{} ({})", name, version, package.source),
+            None => format!("{} ({})", name, package.source),
+        }
+    }
+
+    /// Packages reachable from `roots`, in the order they are first seen
+    fn reachable<'b>(&'b self, roots: &[&'b str]) -> Vec<&'b str> {
+        let mut order = Vec::new();
+        let mut pending: Vec<&str> = roots.iter().rev().copied().collect();
+        while let Some(name) = pending.pop() {
+            if order.contains(&name) {
+                continue;
+            }
+            order.push(name);
+            pending.extend(self.edges[name].iter().rev());
+        }
+        order
+    }
+
+    /// Indented tree per root. Packages that appear again below the same
+    /// root are marked with `(*)` and not expanded again.
+    fn print_text(&self, roots: &[&str]) {
+        let mut duplicates = false;
+        for (i, root) in roots.iter().enumerate() {
+            if i > 0 {
+                println!();
+            }
+            println!("{}", self.label(root));
+            let mut seen = BTreeSet::from([*root]);
+            duplicates |= self.print_children(root, "", &mut seen);
+        }
+
+        if duplicates {
+            println!();
+            println!("(*) duplicate, shown above");
+        }
+    }
+
+    /// Prints the children of `name` below `prefix`, returning whether any
+    /// was marked as a duplicate
+    fn print_children<'a>(&'a self, name: &str, prefix: &str, seen: &mut BTreeSet<&'a str>) -> bool {
+        let mut duplicates = false;
+        let children = &self.edges[name];
+        for (i, child) in children.iter().enumerate() {
+            let last = i + 1 == children.len();
+            let (branch, continuation) = if last { ("└── ", "    ") } else { ("├── ", "│   ") };
+
+            if !seen.insert(child) {
+                println!("{}{}{} (*)", prefix, branch, self.label(child));
+                duplicates = true;
+                continue;
+            }
+            println!("{}{}{}", prefix, branch, self.label(child));
+            duplicates |= self.print_children(child, &format!("{}{}", prefix, continuation), seen);
+        }
+        duplicates
+    }
+
+    fn print_dot(&self, roots: &[&str]) {
+        let escape = |text: &str| text.replace('\\', "\\\\").replace('"', "\\\"");
+        let nodes = self.reachable(roots);
+
+        println!("digraph dependencies {{");
+        for name in &nodes {
+            println!("    \"{}\" [label=\"{}\"];", escape(name), escape(&self.label(name)));
+        }
+        for name in &nodes {
+            for child in &self.edges[name] {
+                println!("    \"{}\" -> \"{}\";", escape(name), escape(child));
+            }
+        }
+        println!("}}");
+    }
+
+    fn print_json(&self, roots: &[&str]) -> Result<()> {
+        let edges_key = if self.inverted { "dependents" } else { "dependencies" };
+        let packages: Vec<serde_json::Value> = self.reachable(roots).into_iter()
+            .map(|name| {
+                let package = self.package(name);
+                let mut value = serde_json::json!({
+                    "name": name,
+                    "version": package.version,
+                    "source": package.source,
+                    "member": package.member,
+                    "header_only": package.header_only,
+                });
+                value[edges_key] = serde_json::json!(self.edges[name]);
+                value
+            })
+            .collect();
+
+        let output = serde_json::json!({ "roots": roots, "packages": packages });
+        println!("{}", serde_json::to_string_pretty(&output).map_err(|e| Error::Library(e.to_string()))?);
+        Ok(())
+    }
+}
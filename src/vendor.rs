@@ -0,0 +1,152 @@
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use toml_edit::DocumentMut;
+
+use crate::checksum;
+use crate::config::Config;
+use crate::error::{Error, Result};
+use crate::lockfile::{LockedPackage, Lockfile};
+use crate::workspace::{Update, Workspace};
+
+/// Written into every vendored package
+pub const VENDOR_METADATA: &str = ".cbuild-vendor.json";
+
+/// Directory `cbuild vendor` writes to, relative to the workspace root
+const DEFAULT_VENDOR_DIR: &str = "vendor";
+
+/// Where a vendored package came from and what it contained
+#[derive(Debug, Deserialize, Serialize)]
+pub struct VendoredPackage {
+    /// Its entry in the lockfile
+    pub locked: LockedPackage,
+
+    /// SHA-256 of the vendored files, see `checksum::sha256_dir`
+    pub files: String,
+}
+
+/// Copies every git, registry and archive dependency of the workspace `root` into
+/// `vendor/` and makes later builds use only those copies
+pub fn vendor(root: &Config) -> Result<()> {
+    let manifest = root.manifest.clone().ok_or(Error::NoConfig())?;
+    let relative_dir = match &root.vendor {
+        Some(dir) => dir.strip_prefix(&root.root).unwrap_or(dir).to_path_buf(),
+        None => PathBuf::from(DEFAULT_VENDOR_DIR),
+    };
+    let vendor_dir = root.root.join(&relative_dir);
+
+    // Resolve from the original sources, not from an earlier vendor/
+    let mut resolving = root.clone();
+    resolving.vendor = None;
+    let workspace = Workspace::load(&resolving, &Update::Locked)?;
+    let lockfile = Lockfile::load(&root.root)?;
+
+    std::fs::create_dir_all(&vendor_dir)?;
+    remove_stale(&vendor_dir, &workspace)?;
+
+    let mut count = 0;
+    for package in workspace.packages.iter().filter(|package| !package.member) {
+        let Some(locked) = lockfile.packages.iter()
+            .find(|locked| locked.name == package.name() && is_vendored_source(&locked.source)) else {
+            continue;
+        };
+
+        let dir = vendor_dir.join(package.name());
+        if dir.exists() {
+            std::fs::remove_dir_all(&dir)?;
+        }
+        copy_dir(&package.config.root, &dir)?;
+
+        let metadata = VendoredPackage { locked: locked.clone(), files: checksum::sha256_dir(&dir)? };
+        let contents = serde_json::to_string_pretty(&metadata).map_err(|e| Error::Library(e.to_string()))?;
+        std::fs::write(dir.join(VENDOR_METADATA), contents)?;
+
+        println!("Vendored {} {}", package.name(), locked.revision());
+        count += 1;
+    }
+
+    if root.vendor.is_none() {
+        let contents = std::fs::read_to_string(&manifest)?;
+        let mut document: DocumentMut = contents.parse().map_err(|e: toml_edit::TomlError| {
+            Error::Config(format!("{}: {}", manifest.display(), e.message().trim()))
+        })?;
+        let mut table = toml_edit::Table::new();
+        table.insert("directory", toml_edit::value(relative_dir.to_string_lossy().replace('\\', "/")));
+        document.insert("vendor", toml_edit::Item::Table(table));
+        std::fs::write(&manifest, document.to_string())?;
+        println!("Added [vendor] to {}", manifest.display());
+    }
+
+    println!("Vendored {} packages to {}, builds now resolve them only from there", count, vendor_dir.display());
+    Ok(())
+}
+
+/// The vendored copy of `name`, verified to come from `source` and to be
+/// unmodified
+pub fn load(vendor_dir: &Path, name: &str, source: &str) -> Result<(VendoredPackage, PathBuf)> {
+    let dir = vendor_dir.join(name);
+    let contents = std::fs::read_to_string(dir.join(VENDOR_METADATA)).map_err(|_| {
+        Error::Library(format!("`{}` is not vendored in {}, run `cbuild vendor`", name, vendor_dir.display()))
+    })?;
+    let metadata: VendoredPackage = serde_json::from_str(&contents).map_err(|e| {
+        Error::Library(format!("Invalid {} of `{}`: {}", VENDOR_METADATA, name, e))
+    })?;
+
+    // Registry packages are vendored from whichever version was resolved,
+    // so only their registry has to match
+    let registry = metadata.locked.source.starts_with("registry+") && source.starts_with("registry+");
+    if !registry && metadata.locked.source != source {
+        return Err(Error::Library(format!("Vendored `{}` comes from {}, but the manifest asks for {}, run `cbuild vendor` again",
+            name, metadata.locked.source, source)));
+    }
+
+    let files = checksum::sha256_dir(&dir)?;
+    if files != metadata.files {
+        return Err(Error::Library(format!("Vendored `{}` in {} was modified (expected checksum {}, found {})",
+            name, dir.display(), metadata.files, files)));
+    }
+
+    Ok((metadata, dir))
+}
+
+/// Sources that are copied to `vendor/`, as opposed to local paths
+fn is_vendored_source(source: &str) -> bool {
+    !source.starts_with("path+") && !source.starts_with("global+")
+}
+
+/// Removes vendored packages the workspace no longer uses
+fn remove_stale(vendor_dir: &Path, workspace: &Workspace) -> Result<()> {
+    for entry in std::fs::read_dir(vendor_dir)? {
+        let path = entry?.path();
+        let name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+        let used = workspace.packages.iter().any(|package| !package.member && package.name() == name);
+        if !used && path.join(VENDOR_METADATA).exists() {
+            std::fs::remove_dir_all(&path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Copies the directory `from` to `to`, leaving out what cbuild generated
+/// at its root
+pub fn copy_dir(from: &Path, to: &Path) -> Result<()> {
+    copy_files(from, to, true)
+}
+
+fn copy_files(from: &Path, to: &Path, root: bool) -> Result<()> {
+    std::fs::create_dir_all(to)?;
+    for entry in std::fs::read_dir(from)? {
+        let path = entry?.path();
+        let name = path.file_name().unwrap();
+        if root && checksum::is_generated(name) {
+            continue;
+        }
+        if path.is_dir() {
+            copy_files(&path, &to.join(name), false)?;
+        }
+        else {
+            std::fs::copy(&path, to.join(name))?;
+        }
+    }
+    Ok(())
+}
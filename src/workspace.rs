@@ -0,0 +1,862 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use semver::{Version, VersionReq};
+
+use crate::archive;
+use crate::build::{self, BuildOutput, Exports};
+use crate::checksum;
+use crate::config::{self, Config, Type};
+use crate::dependency::{Dependency, Source};
+use crate::foreign::{self, ForeignBuild};
+use crate::git;
+use crate::lockfile::{LockedPackage, Lockfile};
+use crate::pkgconfig;
+use crate::registry::{self, Registry, Requirement};
+use crate::vendor;
+use crate::diagnostic::closest_match;
+use crate::error::{Error, Result};
+use crate::{log, GLOBAL_LIB_PATH};
+
+/// A project taking part in a build
+pub struct Package {
+    pub config: Config,
+
+    /// Workspace members, including the root project, as opposed to
+    /// projects only pulled in as dependencies
+    pub member: bool,
+
+    /// Has no manifest and only provides headers
+    pub header_only: bool,
+
+    /// Built and installed by another build system
+    pub foreign: Option<ForeignBuild>,
+
+    /// Names of the packages it depends on directly
+    pub dependencies: Vec<String>,
+
+    /// Where the package comes from, as in the lockfile, with the commit
+    /// of git packages
+    pub source: String,
+
+    /// From the manifest, or the registry for header-only packages
+    pub version: Option<String>,
+}
+
+impl Package {
+    pub fn name(&self) -> &str {
+        self.config.project_name.as_deref().unwrap_or_default()
+    }
+}
+
+/// Every package of a build
+pub struct Workspace {
+    /// Packages ordered so that dependencies come before their dependents
+    pub packages: Vec<Package>,
+}
+
+/// When the project of `config` is a member of a workspace further up,
+/// returns the workspace's config and the member's name instead
+pub fn find_root(config: Config) -> Result<(Config, Option<String>)> {
+    if !config.members.is_empty() {
+        return Ok((config, None));
+    }
+
+    for dir in config.root.ancestors().skip(1) {
+        let Some(manifest) = config::manifest_in(dir) else {
+            continue;
+        };
+        // Unrelated files named `config.toml` may lie further up
+        if !has_workspace_table(&manifest) {
+            continue;
+        }
+
+        let workspace = Config::load_from(&manifest)?;
+        if member_dirs(&workspace)?.contains(&config.root) {
+            return Ok((workspace, config.project_name));
+        }
+    }
+
+    Ok((config, None))
+}
+
+fn has_workspace_table(manifest: &Path) -> bool {
+    std::fs::read_to_string(manifest).ok()
+        .and_then(|contents| contents.parse::<toml::Table>().ok())
+        .is_some_and(|table| table.contains_key("workspace"))
+}
+
+/// Directories matched by the `members` patterns of a workspace
+fn member_dirs(root: &Config) -> Result<Vec<PathBuf>> {
+    let mut dirs = Vec::new();
+    for pattern in &root.members {
+        let full_pattern = root.root.join(pattern);
+        let paths = glob::glob(&full_pattern.to_string_lossy()).map_err(|e| {
+            Error::Config(format!("Invalid glob pattern `{}`: {}", pattern, e.msg))
+        })?;
+
+        let before = dirs.len();
+        for path in paths.flatten() {
+            if path.is_dir() && config::manifest_in(&path).is_some() {
+                dirs.push(path.canonicalize()?);
+            }
+        }
+        if dirs.len() == before {
+            return Err(Error::Config(format!("Workspace member `{}` matches no project directory", pattern)));
+        }
+    }
+
+    dirs.sort();
+    dirs.dedup();
+    Ok(dirs)
+}
+
+impl Workspace {
+    /// Loads the project of `root`, or every member when `root` is a
+    /// workspace, together with all their dependencies, and orders them by
+    /// their dependencies
+    ///
+    /// Git dependencies use the commits recorded in the lockfile, except
+    /// for those selected by `update`, and the lockfile is rewritten with
+    /// the commits in use.
+    pub fn load(root: &Config, update: &Update) -> Result<Self> {
+        let mut resolver = Resolver {
+            root,
+            update,
+            locked: Lockfile::load(&root.root)?,
+            resolved: Lockfile::default(),
+            registry: Registry::new(&root.registry),
+            selected: BTreeMap::new(),
+        };
+
+        let mut packages = Packages::default();
+        if root.project_name.is_some() {
+            packages.add(root.clone(), true, false)?;
+        }
+        for dir in member_dirs(root)? {
+            if dir == root.root {
+                continue;
+            }
+            let manifest = config::manifest_in(&dir).unwrap();
+            let mut member = Config::load_from(&manifest)?;
+            if member.project_name.is_none() {
+                return Err(Error::Config(format!("Workspace member {} has no project name", manifest.display())));
+            }
+            inherit(&mut member, root);
+            if let Some(standard) = root.standard_override.clone() {
+                member.override_standard(standard);
+            }
+            packages.add(member, true, false)?;
+        }
+
+        // Dependencies may pull in further projects, which are resolved in
+        // turn until every package is known. Registry packages are resolved
+        // together once all requirements on them are known, and may add
+        // further requirements of their own.
+        let mut pending: Vec<String> = packages.by_name.keys().cloned().collect();
+        let mut requirements = Vec::new();
+        loop {
+            while let Some(name) = pending.pop() {
+                let config = packages.by_name[&name].config.clone();
+                resolver.copy_libraries(&config)?;
+
+                let mut dependencies = Vec::new();
+                for dependency in &config.dependencies {
+                    if let Source::Registry(requirement) = &dependency.source {
+                        requirements.push(Requirement {
+                            name: dependency.name.clone(),
+                            requirement: requirement.clone(),
+                            required_by: name.clone(),
+                        });
+                        dependencies.push(dependency.name.clone());
+                        continue;
+                    }
+                    if resolver.selected.contains_key(&name) {
+                        return Err(Error::Library(format!(
+                            "Registry package `{}` depends on `{}`, which is not in the registry", name, dependency.name)));
+                    }
+
+                    let dir = resolver.dependency_dir(&config, dependency)?;
+                    let package = match packages.by_root.get(&dir) {
+                        Some(package) => package.clone(),
+                        None => {
+                            let (mut dependency_config, header_only) = load_dependency(dependency, &dir)?;
+                            inherit(&mut dependency_config, root);
+                            let package = packages.add(dependency_config, false, header_only)?;
+                            packages.by_name.get_mut(&package).unwrap().foreign = dependency.build.clone();
+                            pending.push(package.clone());
+                            package
+                        },
+                    };
+
+                    if package != dependency.name {
+                        return Err(Error::Config(format!("Dependency `{}` of `{}` points to project `{}`",
+                            dependency.name, name, package)));
+                    }
+                    let dependency_package = &packages.by_name[&package];
+                    if matches!(dependency.source, Source::Path(_)) && !dependency_package.member {
+                        resolver.lock_path(&package, &dependency_package.config);
+                    }
+                    dependencies.push(package);
+                }
+                packages.by_name.get_mut(&name).unwrap().dependencies = dependencies;
+            }
+
+            if requirements.iter().all(|requirement| packages.by_name.contains_key(&requirement.name)) {
+                break;
+            }
+            for (dependency, dir) in resolver.resolve_registry(&requirements)? {
+                if packages.by_name.contains_key(&dependency.name) {
+                    continue;
+                }
+                let (mut dependency_config, header_only) = load_dependency(&dependency, &dir)?;
+                if dependency_config.project_name.as_deref() != Some(dependency.name.as_str()) {
+                    return Err(Error::Library(format!("Registry package `{}` contains project `{}`",
+                        dependency.name, dependency_config.project_name.unwrap_or_default())));
+                }
+                inherit(&mut dependency_config, root);
+                pending.push(packages.add(dependency_config, false, header_only)?);
+            }
+        }
+
+        for requirement in &requirements {
+            match resolver.selected.get(&requirement.name) {
+                Some(version) if requirement.requirement.matches(version) => (),
+                Some(version) => {
+                    return Err(Error::Library(format!("`{}` requires `{}` {}, but {} is in use",
+                        requirement.required_by, requirement.name, requirement.requirement, version)));
+                },
+                None => {
+                    return Err(Error::Library(format!(
+                        "`{}` requires `{}` from the registry, but it is also a path or git dependency",
+                        requirement.required_by, requirement.name)));
+                },
+            }
+        }
+
+        for package in packages.by_name.values() {
+            for dependency in &package.dependencies {
+                let dependency_package = &packages.by_name[dependency];
+                if !dependency_package.header_only && matches!(dependency_package.config.settings.build_type, Type::Binary) {
+                    return Err(Error::Config(format!(
+                        "`{}` depends on `{}`, which is a binary and cannot be linked", package.name(), dependency)));
+                }
+            }
+        }
+
+        let mut order = Vec::new();
+        for name in packages.by_name.keys() {
+            visit(&packages.by_name, name, &mut Vec::new(), &mut order)?;
+        }
+
+        for package in packages.by_name.values_mut() {
+            package.version = package.config.project_version.clone();
+            if package.member {
+                let relative = relative_path(&root.root, &package.config.root);
+                package.source = Source::Path(if relative.as_os_str().is_empty() { PathBuf::from(".") } else { relative }).to_string();
+                continue;
+            }
+
+            let name = package.name().to_string();
+            let locked = resolver.resolved.packages.iter()
+                .find(|locked| locked.name == name && !locked.source.starts_with("global+"));
+            if let Some(locked) = locked {
+                package.source = match &locked.commit {
+                    Some(commit) => format!("{}#{}", locked.source, git::short_commit(commit)),
+                    None => locked.source.clone(),
+                };
+                package.version = package.version.take().or(locked.version.clone());
+            }
+        }
+
+        if !resolver.resolved.packages.is_empty() || !resolver.locked.packages.is_empty() {
+            resolver.resolved.save(&root.root)?;
+        }
+
+        Ok(Workspace {
+            packages: order.into_iter()
+                .map(|name| packages.by_name.remove(&name).unwrap())
+                .collect(),
+        })
+    }
+
+    pub fn package(&self, name: &str) -> Result<&Package> {
+        self.packages.iter().find(|package| package.name() == name).ok_or_else(|| {
+            let hint = closest_match(name, self.packages.iter().map(Package::name))
+                .map(|candidate| format!(", did you mean `{}`?", candidate))
+                .unwrap_or_default();
+            Error::Arguments(format!("Package `{}` not found in the workspace{}", name, hint))
+        })
+    }
+
+    /// Packages a command applies to: the one named `name`, or every
+    /// member
+    pub fn select(&self, name: Option<&str>) -> Result<Vec<&Package>> {
+        match name {
+            Some(name) => Ok(vec![self.package(name)?]),
+            None => Ok(self.packages.iter().filter(|package| package.member).collect()),
+        }
+    }
+
+    /// The package `cbuild run` starts: the one named `name`, or the only
+    /// binary member
+    pub fn runnable(&self, name: Option<&str>) -> Result<&Package> {
+        if let Some(name) = name {
+            return self.package(name);
+        }
+
+        let members = self.select(None)?;
+        let binaries: Vec<&Package> = members.iter().copied()
+            .filter(|package| matches!(package.config.settings.build_type, Type::Binary))
+            .collect();
+        match binaries.as_slice() {
+            [binary] => Ok(binary),
+            [] => members.last().copied().ok_or_else(|| Error::Config("Workspace has no packages".to_string())),
+            _ => Err(Error::Arguments(format!("Workspace has several binaries ({}), choose one with `-p NAME`",
+                binaries.iter().map(|package| package.name()).collect::<Vec<_>>().join(", ")))),
+        }
+    }
+
+    /// Headers and libraries each package is built against, including its
+    /// system dependencies
+    pub fn imports(&self) -> Result<BTreeMap<String, Exports>> {
+        let mut exports: BTreeMap<String, Exports> = BTreeMap::new();
+        let mut imports = BTreeMap::new();
+        for package in &self.packages {
+            let mut package_imports = Exports::default();
+            for dependency in &package.config.system_dependencies {
+                package_imports.extend(&pkgconfig::probe(&package.config, dependency)?);
+            }
+            for dependency in &package.dependencies {
+                package_imports.extend(&exports[dependency]);
+            }
+
+            let package_exports = if package.header_only {
+                Exports {
+                    include_dirs: vec![build::public_include_dir(&package.config)],
+                    ..Exports::default()
+                }
+            }
+            else if package.foreign.is_some() {
+                foreign::exports(&package.config)
+            }
+            else {
+                build::exports(&package.config, &package_imports)
+            };
+            exports.insert(package.name().to_string(), package_exports);
+            imports.insert(package.name().to_string(), package_imports);
+        }
+        Ok(imports)
+    }
+
+    /// Builds `selected` and everything they depend on, dependencies first
+    pub fn build(&self, selected: &[&Package]) -> Result<BTreeMap<String, BuildOutput>> {
+        let mut needed: Vec<&str> = selected.iter().map(|package| package.name()).collect();
+        // Dependents come after their dependencies, so one backwards pass
+        // collects everything needed
+        for package in self.packages.iter().rev() {
+            if needed.contains(&package.name()) {
+                needed.extend(package.dependencies.iter().map(String::as_str));
+            }
+        }
+
+        // Foreign packages have no dependencies of their own, and what they
+        // export is only known once they are installed
+        for package in &self.packages {
+            if let Some(foreign) = package.foreign.as_ref().filter(|_| needed.contains(&package.name())) {
+                foreign::build(&package.config, foreign)?;
+            }
+        }
+
+        let imports = self.imports()?;
+        let mut outputs: BTreeMap<String, BuildOutput> = BTreeMap::new();
+        for package in &self.packages {
+            if needed.contains(&package.name()) && !package.header_only && package.foreign.is_none() {
+                // Whether a dependency has C++ sources is known from its build
+                let mut package_imports = imports[package.name()].clone();
+                package_imports.needs_cpp |= package.dependencies.iter()
+                    .any(|dependency| outputs.get(dependency).is_some_and(|output| output.has_cpp));
+
+                // Dependencies are not worked on, and may be shared caches
+                // or vendored copies that must stay unchanged
+                let output = build::build_project(&package.config, &package_imports, package.member)?;
+                outputs.insert(package.name().to_string(), output);
+            }
+        }
+
+        Ok(outputs)
+    }
+}
+
+/// Resolves the dependencies selected by `update` again, reporting the
+/// revisions that changed in the lockfile
+pub fn update(root: &Config, update: Update) -> Result<()> {
+    if let Some(vendor) = &root.vendor {
+        return Err(Error::Library(format!(
+            "Dependencies are vendored in {}, remove `[vendor]` from the manifest to update them and run `cbuild vendor` again",
+            vendor.display())));
+    }
+
+    let before = Lockfile::load(&root.root)?;
+    if let Update::Packages(names) = &update {
+        for name in names {
+            if !before.packages.iter().any(|package| &package.name == name) {
+                return Err(Error::Arguments(format!("`{}` is not a locked dependency", name)));
+            }
+        }
+    }
+
+    Workspace::load(root, &update)?;
+    let after = Lockfile::load(&root.root)?;
+
+    for package in &after.packages {
+        match before.packages.iter().find(|old| old.name == package.name) {
+            Some(old) if old.revision() == package.revision() => (),
+            Some(old) => println!("Updating {} {} -> {}", package.name, old.revision(), package.revision()),
+            None => println!("Locking {} {}", package.name, package.revision()),
+        }
+    }
+    for package in &before.packages {
+        if !after.packages.iter().any(|new| new.name == package.name) {
+            println!("Removing {} {}", package.name, package.revision());
+        }
+    }
+
+    Ok(())
+}
+
+/// Settings every member and dependency takes from the root project, so
+/// that all of them are built into one tree and can be linked together
+fn inherit(config: &mut Config, root: &Config) {
+    config.settings.compiler = root.settings.compiler.clone();
+    config.settings.target = root.settings.target.clone();
+    config.profile = root.profile.clone();
+    config.target_dir = root.target_dir.clone();
+    config.verbose = root.verbose;
+    config.jobs = root.jobs;
+    config.keep_going = root.keep_going;
+}
+
+/// Packages found so far while loading a workspace
+#[derive(Default)]
+struct Packages {
+    by_name: BTreeMap<String, Package>,
+
+    /// Package names by project directory
+    by_root: BTreeMap<PathBuf, String>,
+}
+
+impl Packages {
+    /// Adds the project of `config`, returning its name
+    fn add(&mut self, config: Config, member: bool, header_only: bool) -> Result<String> {
+        let name = config.project_name.clone().unwrap_or_default();
+        if let Some(existing) = self.by_name.get(&name) {
+            return Err(Error::Config(format!("Two packages are named `{}`: {} and {}",
+                name, existing.config.root.display(), config.root.display())));
+        }
+
+        self.by_root.insert(config.root.clone(), name.clone());
+        self.by_name.insert(name.clone(), Package {
+            config,
+            member,
+            header_only,
+            foreign: None,
+            dependencies: Vec::new(),
+            source: String::new(),
+            version: None,
+        });
+        Ok(name)
+    }
+}
+
+/// Which dependencies `Workspace::load` resolves again instead of using
+/// the commits recorded in the lockfile
+pub enum Update {
+    Locked,
+    All,
+    Packages(Vec<String>),
+}
+
+impl Update {
+    fn includes(&self, name: &str) -> bool {
+        match self {
+            Update::Locked => false,
+            Update::All => true,
+            Update::Packages(names) => names.iter().any(|update| update == name),
+        }
+    }
+}
+
+/// Locates the sources of dependencies, fetching them where necessary
+struct Resolver<'a> {
+    root: &'a Config,
+    update: &'a Update,
+
+    /// Lockfile as found on disk
+    locked: Lockfile,
+
+    /// Revisions actually in use, which replace the lockfile
+    resolved: Lockfile,
+
+    registry: Registry,
+
+    /// Versions of the registry packages in use
+    selected: BTreeMap<String, Version>,
+}
+
+impl Resolver<'_> {
+    /// Entry of the lockfile to reuse for `name`, unless it is being
+    /// updated
+    fn locked(&self, name: &str, source: &str) -> Option<LockedPackage> {
+        self.resolved.find(name, source)
+            .or_else(|| self.locked.find(name, source).filter(|_| !self.update.includes(name)))
+            .cloned()
+    }
+
+    /// Project directory of a dependency of `config`
+    fn dependency_dir(&mut self, config: &Config, dependency: &Dependency) -> Result<PathBuf> {
+        match &dependency.source {
+            Source::Path(path) => {
+                let dir = config.root.join(path);
+                dir.canonicalize().map_err(|_| {
+                    Error::Config(format!("Dependency `{}` of `{}` not found at {}",
+                        dependency.name, config.project_name.as_deref().unwrap_or_default(), dir.display()))
+                })
+            },
+            Source::Git { url, reference } => {
+                let source = dependency.source.to_string();
+                if self.root.vendor.is_some() {
+                    return self.vendored(&dependency.name, &source);
+                }
+                let locked = self.locked(&dependency.name, &source);
+
+                let commit = match locked.as_ref().and_then(|package| package.commit.clone()) {
+                    Some(commit) => commit,
+                    None => git::resolve(self.root, url, reference)?,
+                };
+                let mut dir = git::checkout(self.root, url, &commit)?;
+                // Copies of global libraries are locked on their own
+                let copies = library_copies(dependency, &dir);
+                let mut checksum = checksum::sha256_dir_except(&dir, &copies)?;
+
+                if let Some(expected) = locked.and_then(|package| package.checksum).filter(|expected| *expected != checksum) {
+                    // The cached checkout may have been edited, so check out
+                    // the commit again before giving up
+                    std::fs::remove_dir_all(&dir)?;
+                    dir = git::checkout(self.root, url, &commit)?;
+                    checksum = checksum::sha256_dir_except(&dir, &copies)?;
+                    if checksum != expected {
+                        return Err(Error::Library(format!(
+                            "Checksum of `{}` at {} does not match cbuild.lock (expected {}, found {}), run `cbuild update {}` if this is intended",
+                            dependency.name, git::short_commit(&commit), expected, checksum, dependency.name)));
+                    }
+                }
+
+                self.resolved.insert(LockedPackage {
+                    name: dependency.name.clone(),
+                    source,
+                    version: None,
+                    commit: Some(commit),
+                    checksum: Some(checksum),
+                });
+                Ok(dir)
+            },
+            Source::Archive { url, sha256 } => {
+                let source = dependency.source.to_string();
+                let dir = if self.root.vendor.is_some() {
+                    let dir = self.vendored(&dependency.name, &source)?;
+                    let vendored = self.resolved.find(&dependency.name, &source).and_then(|package| package.checksum.clone());
+                    if vendored.as_ref() != Some(sha256) {
+                        return Err(Error::Library(format!(
+                            "Vendored `{}` has a different checksum than the manifest asks for, run `cbuild vendor` again",
+                            dependency.name)));
+                    }
+                    dir
+                }
+                else {
+                    let locked = self.locked(&dependency.name, &source).and_then(|package| package.checksum);
+                    if let Some(expected) = locked.filter(|expected| expected != sha256) {
+                        return Err(Error::Library(format!(
+                            "Checksum of `{}` in the manifest does not match cbuild.lock (expected {}, found {}), run `cbuild update {}` if this is intended",
+                            dependency.name, expected, sha256, dependency.name)));
+                    }
+
+                    // Local archives are relative to the depending project
+                    let location = if config::is_url(url) || url.starts_with("file://") {
+                        url.clone()
+                    }
+                    else {
+                        config.root.join(url).to_string_lossy().to_string()
+                    };
+                    archive::fetch(self.root, &dependency.name, &location, sha256)?
+                };
+
+                self.resolved.insert(LockedPackage {
+                    name: dependency.name.clone(),
+                    source,
+                    version: None,
+                    commit: None,
+                    checksum: Some(sha256.clone()),
+                });
+                Ok(dir)
+            },
+            Source::Registry(_) => unreachable!("registry dependencies are resolved together by `resolve_registry`"),
+        }
+    }
+
+    /// Picks versions for the registry packages `requirements` refer to and
+    /// returns them with their extracted sources. Packages selected earlier
+    /// are already loaded and keep their version.
+    fn resolve_registry(&mut self, requirements: &[Requirement]) -> Result<Vec<(Dependency, PathBuf)>> {
+        let source = self.registry.source();
+        if self.root.vendor.is_some() {
+            let mut resolved = Vec::new();
+            for requirement in requirements {
+                if self.selected.contains_key(&requirement.name) {
+                    continue;
+                }
+                let dir = self.vendored(&requirement.name, &source)?;
+                let version = self.resolved.find(&requirement.name, &source)
+                    .and_then(|package| Version::parse(package.version.as_deref()?).ok())
+                    .ok_or_else(|| Error::Library(format!("Vendored `{}` has no version", requirement.name)))?;
+                self.selected.insert(requirement.name.clone(), version);
+                resolved.push((Dependency {
+                    name: requirement.name.clone(),
+                    source: Source::Registry(requirement.requirement.clone()),
+                    build: None,
+                }, dir));
+            }
+            return Ok(resolved);
+        }
+        let preferred: BTreeMap<String, Version> = self.locked.packages.iter()
+            .filter(|package| package.source == source && !self.update.includes(&package.name))
+            .filter_map(|package| Some((package.name.clone(), Version::parse(package.version.as_deref()?).ok()?)))
+            .collect();
+
+        let mut resolved = Vec::new();
+        for (name, entry) in registry::resolve(&mut self.registry, self.root, requirements, &preferred, &self.selected)? {
+            let locked = self.locked(&name, &source)
+                .filter(|package| package.version.as_deref() == Some(entry.version.as_str()));
+            if let Some(expected) = locked.and_then(|package| package.checksum).filter(|expected| *expected != entry.checksum) {
+                return Err(Error::Library(format!(
+                    "Checksum of `{}` {} in the registry does not match cbuild.lock (expected {}, found {})",
+                    name, entry.version, expected, entry.checksum)));
+            }
+
+            let dir = self.registry.download(self.root, &entry)?;
+            let version = Version::parse(&entry.version).unwrap();
+            self.resolved.insert(LockedPackage {
+                name: name.clone(),
+                source: source.clone(),
+                version: Some(entry.version.clone()),
+                commit: None,
+                checksum: Some(entry.checksum.clone()),
+            });
+            self.selected.insert(name.clone(), version.clone());
+            resolved.push((Dependency {
+                name,
+                source: Source::Registry(VersionReq::parse(&format!("={}", version)).unwrap()),
+                build: None,
+            }, dir));
+        }
+
+        Ok(resolved)
+    }
+
+    /// Directory of the vendored copy of `name`, recording the revision it
+    /// was vendored at
+    fn vendored(&mut self, name: &str, source: &str) -> Result<PathBuf> {
+        let (metadata, dir) = vendor::load(self.root.vendor.as_ref().unwrap(), name, source)?;
+        self.resolved.insert(metadata.locked);
+        Ok(dir)
+    }
+
+    /// Records a path dependency loaded from `config`, which is local code
+    /// and therefore locked by version only
+    fn lock_path(&mut self, name: &str, config: &Config) {
+        self.resolved.insert(LockedPackage {
+            name: name.to_string(),
+            source: Source::Path(relative_path(&self.root.root, &config.root)).to_string(),
+            version: config.project_version.clone(),
+            commit: None,
+            checksum: None,
+        });
+    }
+
+    /// Copies the `libraries` of `config` from the global library path into
+    /// its `lib/` directory, refreshing copies that differ from the locked
+    /// version
+    fn copy_libraries(&mut self, config: &Config) -> Result<()> {
+        if config.libraries.is_empty() {
+            return Ok(());
+        }
+        log(config, "Managing dependencies");
+
+        let project_lib_path = config.root.join("lib");
+        std::fs::create_dir_all(&project_lib_path)?;
+
+        let global_lib_path = PathBuf::from(shellexpand::tilde(GLOBAL_LIB_PATH).to_string());
+        let source = format!("global+{}", GLOBAL_LIB_PATH);
+
+        for lib in &config.libraries {
+            let global_lib_file = global_lib_path.join(lib);
+            let project_lib_file = project_lib_path.join(lib);
+            let not_found = || Error::Library(format!("Library {} not found in global library path", lib));
+
+            let checksum = match self.locked(lib, &source).and_then(|package| package.checksum) {
+                Some(checksum) => checksum,
+                None if global_lib_file.exists() => checksum::sha256_file(&global_lib_file)?,
+                None => return Err(not_found()),
+            };
+
+            if !project_lib_file.exists() || checksum::sha256_file(&project_lib_file)? != checksum {
+                if !global_lib_file.exists() {
+                    return Err(not_found());
+                }
+                if checksum::sha256_file(&global_lib_file)? != checksum {
+                    return Err(Error::Library(format!(
+                        "Library {} in the global library path does not match cbuild.lock, run `cbuild update {}` to use it",
+                        lib, lib)));
+                }
+                std::fs::copy(&global_lib_file, &project_lib_file)?;
+                println!("Copied dependency: {} to project", lib);
+            }
+
+            self.resolved.insert(LockedPackage {
+                name: lib.clone(),
+                source: source.clone(),
+                version: None,
+                commit: None,
+                checksum: Some(checksum),
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// Path of `path` relative to the directory `base`, both absolute
+pub fn relative_path(base: &Path, path: &Path) -> PathBuf {
+    let common = base.components().zip(path.components()).take_while(|(a, b)| a == b).count();
+    let mut relative: PathBuf = base.components().skip(common).map(|_| "..").collect();
+    relative.extend(path.components().skip(common));
+    relative
+}
+
+/// Loads the project in `dir` that a package depends on, which is
+/// header-only when it has no manifest. Foreign projects are libraries
+/// whatever manifest they have.
+fn load_dependency(dependency: &Dependency, dir: &Path) -> Result<(Config, bool)> {
+    if dependency.build.is_some() {
+        let mut config = Config {
+            project_name: Some(dependency.name.clone()),
+            root: dir.to_path_buf(),
+            ..Config::default()
+        };
+        config.settings.build_type = Type::Library;
+        return Ok((config, false));
+    }
+
+    let Some(manifest) = config::manifest_in(dir) else {
+        let config = Config {
+            project_name: Some(dependency.name.clone()),
+            root: dir.to_path_buf(),
+            ..Config::default()
+        };
+        return Ok((config, true));
+    };
+
+    let dependency_config = Config::load_from(&manifest)?;
+    if dependency_config.project_name.is_none() {
+        return Err(Error::Config(format!("Dependency `{}` at {} has no project name",
+            dependency.name, manifest.display())));
+    }
+
+    Ok((dependency_config, false))
+}
+
+/// Files in `dir` that `Resolver::copy_libraries` copies the global
+/// libraries of the dependency in it to, relative to `dir`
+fn library_copies(dependency: &Dependency, dir: &Path) -> Vec<PathBuf> {
+    load_dependency(dependency, dir)
+        .map(|(config, _)| config.libraries.iter().map(|library| Path::new("lib").join(library)).collect())
+        .unwrap_or_default()
+}
+
+/// Depth-first topological sort, reporting dependency cycles
+fn visit(packages: &BTreeMap<String, Package>, name: &str, chain: &mut Vec<String>, order: &mut Vec<String>) -> Result<()> {
+    if order.iter().any(|done| done == name) {
+        return Ok(());
+    }
+    if chain.iter().any(|seen| seen == name) {
+        chain.push(name.to_string());
+        return Err(Error::Config(format!("Dependency cycle: {}", chain.join(" -> "))));
+    }
+
+    chain.push(name.to_string());
+    for dependency in &packages[name].dependencies {
+        visit(packages, dependency, chain, order)?;
+    }
+    chain.pop();
+
+    order.push(name.to_string());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(path: &Path, contents: &str) {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, contents).unwrap();
+    }
+
+    fn git(dir: &Path, args: &[&str]) {
+        let status = std::process::Command::new("git")
+            .args(["-c", "user.name=cbuild", "-c", "user.email=cbuild@localhost"])
+            .args(args)
+            .current_dir(dir)
+            .status()
+            .unwrap();
+        assert!(status.success());
+    }
+
+    #[test]
+    fn library_copies_do_not_invalidate_git_checkouts() {
+        let temp = std::env::temp_dir().join(format!("cbuild-test-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&temp);
+        // The git cache and the global libraries live in the home directory
+        std::env::set_var("HOME", temp.join("home"));
+        write(&temp.join("home/.cbuild/libs/dummy.h"), "#define DUMMY 1\n");
+
+        let repository = temp.join("gl");
+        write(&repository.join("cbuild.toml"), "[project]\nname = \"gl\"\n\n[settings]\ntype = \"lib\"\nlibraries = [\"dummy.h\"]\n");
+        write(&repository.join("src/gl.c"), "int gl(void) { return 1; }\n");
+        git(&repository, &["init", "--quiet"]);
+        git(&repository, &["add", "-A"]);
+        git(&repository, &["commit", "--quiet", "-m", "Initial commit"]);
+
+        let manifest = temp.join("app/cbuild.toml");
+        write(&manifest, &format!("[project]\nname = \"app\"\n\n[dependencies]\ngl = {{ git = \"{}\" }}\n",
+            repository.display()));
+        write(&temp.join("app/src/main.c"), "int main(void) { return 0; }\n");
+
+        let checkout = |workspace: &Workspace| {
+            workspace.packages.iter().find(|package| package.name() == "gl").unwrap().config.root.clone()
+        };
+        let config = Config::load_from(&manifest).unwrap();
+        let dir = checkout(&Workspace::load(&config, &Update::Locked).unwrap());
+        assert!(dir.join("lib/dummy.h").is_file());
+
+        // A new checkout would lose this file, which isn't part of the
+        // checksum
+        let marker = dir.join(".git").join("cbuild-test-marker");
+        std::fs::write(&marker, "").unwrap();
+
+        let dir = checkout(&Workspace::load(&config, &Update::Locked).unwrap());
+        assert!(marker.is_file(), "{} was checked out again", dir.display());
+
+        std::fs::remove_dir_all(&temp).unwrap();
+    }
+}